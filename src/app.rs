@@ -1,7 +1,16 @@
-use clap::{arg, ArgAction, Command};
+use clap::{arg, builder::PossibleValuesParser, value_parser, Arg, ArgAction, Command};
+use clap_complete::Shell;
+
+use crate::presets::presets;
+use crate::report::{Format, Stream};
 use owo_colors::colored::*;
 
-pub fn up() -> Command {
+use std::time::Duration;
+
+// "programs" are the names from the config file, used to validate and complete arguments
+pub fn up(programs: &[String]) -> Command {
+    let names = PossibleValuesParser::new(programs.to_vec());
+
     Command::new("up")
         .bin_name("up")
         .before_help(format!(
@@ -13,20 +22,138 @@ pub fn up() -> Command {
         // TODO update version
         .version("1.0.3")
         .author("Leann Phydon <leann.phydon@gmail.com")
+        .subcommand(
+            Command::new("add")
+                .short_flag('a')
+                .long_flag("add")
+                .about("Add a program to the config file (prompts for every field without --name)")
+                .args(app_args())
+        )
+        .subcommand(
+            Command::new("check")
+                .short_flag('C')
+                .long_flag("check")
+                .visible_alias("validate")
+                .about("Check the config file for errors and common mistakes")
+        )
         .subcommand(
             Command::new("clean")
                 .short_flag('c')
                 .long_flag("clean")
                 .about("Remove all temporary files")
+                .arg(
+                    arg!(--"older-than" <AGE> "Only remove files older than this (e.g. 30m, 12h, 7d, 2w)")
+                        .value_parser(parse_age)
+                )
+                .arg(
+                    arg!(--program <PROGRAMS> "Only remove the files of these programs (comma separated)")
+                        .value_parser(names.clone())
+                        .hide_possible_values(true)
+                        .value_delimiter(',')
+                )
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a shell completion script (includes the program names from the config)")
+                .arg(
+                    arg!(<SHELL> "The shell to generate the script for")
+                        .value_parser(value_parser!(Shell))
+                )
+                .arg_required_else_help(true)
         )
         .arg(arg!(-v --verbose "show output").action(ArgAction::SetTrue))
-        // .subcommand(
-        //     Command::new("exclude")
-        //         .about("Exclude programs from update")
-        //         .short_flag('e'),
-            // .arg(arg!(<PROGRAM> "The programs to exclude from updates").num_args(1..))
-            // .arg_required_else_help(true),
-        // )
+        .arg(
+            arg!(-t --timeout <SECONDS> "Kill programs running longer than this \nPrograms with their own timeout in the config keep it")
+                .value_parser(value_parser!(u64))
+                .global(true)
+        )
+        .arg(
+            arg!(-R --retries <N> "Run failed programs up to N more times \nPrograms with their own retries in the config keep it")
+                .value_parser(value_parser!(u32))
+                .global(true)
+        )
+        .arg(
+            arg!(-j --jobs <N> "Run at most N programs at the same time")
+                .value_parser(value_parser!(u64).range(1..))
+                .global(true)
+        )
+        .arg(
+            arg!(-n --"dry-run" "Print the commands that would run without running them")
+                .action(ArgAction::SetTrue)
+                .global(true)
+        )
+        .arg(
+            arg!(--skip <PROGRAMS> "Leave out these programs (comma separated)")
+                .value_parser(names.clone())
+                .hide_possible_values(true)
+                .value_delimiter(',')
+                .global(true)
+        )
+        .arg(
+            arg!(--only <PROGRAMS> "Run only these programs (comma separated)")
+                .value_parser(names.clone())
+                .hide_possible_values(true)
+                .value_delimiter(',')
+                .global(true)
+        )
+        .arg(
+            arg!(--plain "Print one timestamped line per state change instead of spinners \nUsed automatically without a terminal")
+                .action(ArgAction::SetTrue)
+                .global(true)
+        )
+        .arg(
+            arg!(--stream [MODE] "Show the output of the programs while they run \n\"lines\" prints every line, \"spinner\" the latest one next to the spinner")
+                .value_parser(value_parser!(Stream))
+                .default_missing_value("lines")
+                .require_equals(true)
+                .global(true)
+        )
+        .arg(
+            arg!(-y --yes "Answer yes to every confirmation (clean, remove, init)")
+                .action(ArgAction::SetTrue)
+                .global(true)
+        )
+        .arg(
+            arg!(--format <FORMAT> "Print the results as text or JSON \nUsed by list, sys, info, outdated, update, history and log")
+                .value_parser(value_parser!(Format))
+                .default_value("text")
+                .global(true)
+        )
+        .subcommand(
+            Command::new("edit")
+                .short_flag('E')
+                .long_flag("edit")
+                .about("Edit a program in the config file (prompts for every field without flags)")
+                .arg(arg!(<PROGRAM> "The program to edit").value_parser(names.clone()))
+                .args(app_args())
+                .arg_required_else_help(true)
+        )
+        .subcommand(
+            Command::new("exclude")
+                .short_flag('e')
+                .long_flag("exclude")
+                .about("Update all programs except the specified ones")
+                .arg(
+                    arg!(<PROGRAM> "The programs to exclude from the update")
+                        .value_parser(names.clone())
+                        .num_args(1..)
+                )
+                .arg_required_else_help(true)
+        )
+        .subcommand(
+            Command::new("history")
+                .short_flag('H')
+                .long_flag("history")
+                .about("List past runs or show the details of one")
+                .arg(
+                    arg!([RUN] "The run to show (an id from the list, the start of one or \"last\")")
+                )
+                .arg(
+                    arg!(--versions [PROGRAMS] "List every version change instead, optionally only of these programs (comma separated)")
+                        .num_args(0..)
+                        .value_delimiter(',')
+                )
+        )
         .subcommand(
             Command::new("info")
                 .about("Get status information (saved in output files)")
@@ -36,8 +163,19 @@ pub fn up() -> Command {
                     arg!(-v --verbose "show output")
                         .action(ArgAction::SetTrue)
                 )
-            // .arg(arg!(<PROGRAM> "The program to get information about"))
-            // .arg_required_else_help(true),
+                .arg(
+                    arg!([PROGRAM] "The programs to get information about, all if none are given")
+                        .value_parser(names.clone())
+                        .num_args(0..)
+                )
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Write a new config file (shows the changes before overwriting the current one)")
+                .arg(
+                    arg!(-d --detect "Add every installed package manager with a preset")
+                        .action(ArgAction::SetTrue)
+                )
         )
         .subcommand(
             Command::new("list")
@@ -59,10 +197,136 @@ pub fn up() -> Command {
                 .arg(arg!(<PROGRAM> "The program for which the output should be displayed \nEnter \"all\" to open all available output files"))
                 .arg_required_else_help(true)
         )
+        .subcommand(
+            Command::new("outdated")
+                .about("List the pending updates without updating anything")
+                .short_flag('O')
+                .long_flag("outdated")
+                .arg(
+                    arg!(-v --verbose "show output")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    arg!([PROGRAM] "The programs to check, all if none are given")
+                        .value_parser(names.clone())
+                        .num_args(0..)
+                )
+        )
+        .subcommand(
+            Command::new("presets")
+                .short_flag('P')
+                .long_flag("presets")
+                .about("List the built-in presets for common package managers")
+        )
+        .subcommand(
+            Command::new("remove")
+                .short_flag('r')
+                .long_flag("remove")
+                .about("Remove a program from the config file")
+                .arg(arg!(<PROGRAM> "The program to remove").value_parser(names.clone()))
+                .arg_required_else_help(true)
+        )
         .subcommand(
             Command::new("sys")
                 .short_flag('s')
                 .long_flag("sys")
                 .about("Show system information")
         )
+        .subcommand(
+            Command::new("update")
+                .short_flag('u')
+                .long_flag("update")
+                .about("Update programs (default if no command is given)")
+                .arg(
+                    arg!(-v --verbose "show output")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    arg!([PROGRAM] "The programs to update, all if none are given")
+                        .value_parser(names)
+                        .num_args(0..)
+                )
+        )
+}
+
+// the fields of an "App" in the config file, the timeout and retries are taken from the global flags
+fn app_args() -> [Arg; 9] {
+    let presets: Vec<_> = presets().iter().map(|preset| preset.name).collect();
+
+    [
+        arg!(--preset <PRESET> "A known package manager, fills in every field that is not given")
+            .value_parser(PossibleValuesParser::new(presets)),
+        arg!(--name <NAME> "A custom name for the program"),
+        arg!(--symbol <SYMBOL> "The symbol shown in front of the name (default: first character of the name)"),
+        arg!(--executer <EXECUTER> "The actual program to call from the command line"),
+        arg!(--"start-extern" <BOOL> "Whether an external program gets started (default: true)")
+            .value_parser(value_parser!(bool)),
+        arg!(--"has-output" <BOOL> "Whether the output gets written to a temporary file (default: true)")
+            .value_parser(value_parser!(bool)),
+        arg!(--update <CMD> "The command to update the program"),
+        arg!(--info <CMD> "The command to get status information about the program"),
+        arg!(--after <PROGRAMS> "Programs that have to be updated first (comma separated)")
+            .value_delimiter(','),
+    ]
+}
+
+// an age like "30m", "12h", "7d" or "2w", plain numbers are days
+fn parse_age(age: &str) -> Result<Duration, String> {
+    let age = age.trim();
+    let (number, unit) = match age.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => age.split_at(idx),
+        None => (age, "d"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("\"{}\" is not an age like 30m, 12h, 7d or 2w", age))?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown unit \"{}\", use s, m, h, d or w", unit)),
+    };
+
+    number
+        .checked_mul(secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("\"{}\" is too long ago", age))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn age_units() {
+        assert_eq!(parse_age("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_age("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse_age("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_age("2w"), Ok(Duration::from_secs(14 * 24 * 60 * 60)));
+    }
+
+    #[test]
+    fn plain_numbers_are_days() {
+        assert_eq!(parse_age("3"), parse_age("3d"));
+        assert_eq!(parse_age(" 3 "), parse_age("3d"));
+    }
+
+    #[test]
+    fn invalid_ages() {
+        assert!(parse_age("").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("7y").is_err());
+        assert!(parse_age("-7d").is_err());
+    }
+
+    #[test]
+    fn ages_that_overflow() {
+        assert!(parse_age(&format!("{}w", u64::MAX / 60)).is_err());
+        assert!(parse_age(&format!("{}", u64::MAX)).is_err());
+        assert!(parse_age("99999999999999999999999s").is_err());
+        assert!(parse_age(&format!("{}s", u64::MAX)).is_ok());
+    }
 }
@@ -1,14 +1,28 @@
-use crate::programs::Program;
+use crate::dir_work::{apply_retention, check_create_tmp_dir};
+use crate::history::{record_run, ProgramRun, Run};
+use crate::presets::presets;
+use crate::programs::{Cmd, Program, Settings, State, MAX_RETRY_DELAY};
+use crate::report::{print_entries, print_text, print_value, Format, Stream};
 
-use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+use chrono::Local;
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::error;
 use owo_colors::colored::*;
+use serde::Serialize;
 use sysinfo::{CpuRefreshKind, RefreshKind, System, SystemExt};
 
 use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    env,
     error::Error,
-    io,
-    process::Command,
-    sync::Arc,
+    io::{self, BufRead, BufReader, Read},
+    panic::{self, AssertUnwindSafe},
+    process::{Command, Output, Stdio},
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Condvar, Mutex,
+    },
     thread,
     time::{Duration, Instant},
 };
@@ -40,55 +54,921 @@ const F10: u8 = 127;
 const F11: u8 = 111;
 const F12: u8 = 219;
 
-pub fn init(commands: Vec<Program>, mode: &str) -> Result<(), Box<dyn Error>> {
+// how long the output of a finished or killed program may still take to arrive
+const PIPE_GRACE: Duration = Duration::from_secs(2);
+
+// the pids of the programs running right now and whether each leads its own process group,
+// killed when up itself gets interrupted
+static RUNNING: Mutex<Vec<(u32, bool)>> = Mutex::new(Vec::new());
+
+pub fn init(
+    commands: Vec<Program>,
+    mode: &str,
+    settings: &Settings,
+) -> Result<Vec<Program>, Box<dyn Error>> {
     let num = commands.len() as u64;
-    match mode {
+    // without a limit every program gets its own worker
+    let jobs = settings.max_parallel.unwrap_or(commands.len());
+    let format = settings.format;
+    let started = Local::now();
+    // a failing after_all hook fails the run, but only once it is recorded
+    let mut after_all = Ok(());
+    let finished = match mode {
         "update" => {
-            println!(
-                "{} {}",
-                "↗",
-                // "STARTING UPDATE".bold().truecolor(250, 0, 104)
-                "STARTING UPDATE".bold()
-            );
-            progress_bar(commands, num, "update")?;
+            if format.is_text() {
+                print_event(
+                    &format!(
+                        "↗ {}",
+                        // "STARTING UPDATE".bold().truecolor(250, 0, 104)
+                        "STARTING UPDATE".bold()
+                    ),
+                    settings.plain,
+                );
+            }
+            run_global_hooks(&settings.before_all, "before_all", format, settings.plain)?;
+            let finished = progress_bar(
+                commands,
+                num,
+                "update",
+                jobs,
+                format,
+                settings.plain,
+                settings.stream,
+            )?;
+            after_all = run_global_hooks(&settings.after_all, "after_all", format, settings.plain);
+            finished
         }
         "info" => {
-            println!(
-                "{} {}",
-                "🛈",
-                // "GETTING INFORMATION".bold().truecolor(250, 0, 104)
-                "GETTING INFORMATION".bold()
-            );
-            progress_bar(commands, num, "info")?;
+            if format.is_text() {
+                print_event(
+                    &format!(
+                        "🛈 {}",
+                        // "GETTING INFORMATION".bold().truecolor(250, 0, 104)
+                        "GETTING INFORMATION".bold()
+                    ),
+                    settings.plain,
+                );
+            }
+            progress_bar(
+                commands,
+                num,
+                "info",
+                jobs,
+                format,
+                settings.plain,
+                settings.stream,
+            )?
+        }
+        "outdated" => {
+            if format.is_text() {
+                print_event(
+                    &format!("⇡ {}", "CHECKING FOR PENDING UPDATES".bold()),
+                    settings.plain,
+                );
+            }
+            let finished = progress_bar(
+                commands,
+                num,
+                "outdated",
+                jobs,
+                format,
+                settings.plain,
+                settings.stream,
+            )?;
+            if format.is_text() {
+                print_outdated(&finished, settings.plain);
+            }
+            finished
         }
         _ => {
             unreachable!();
         }
+    };
+
+    let run = Run::new(mode, started, &finished);
+    // jsonl already printed every program as soon as it finished
+    if format == Format::Json {
+        print_value(&run, format);
     }
+    // the run itself went fine, a missing history entry shouldn't fail it
+    if let Err(err) = record_run(&run) {
+        error!("Unable to store the run in the history: {}", err);
+    }
+    if settings.keep_runs.is_some() || settings.keep_days.is_some() {
+        let removed = check_create_tmp_dir()
+            .and_then(|tmp| apply_retention(&tmp, settings.keep_runs, settings.keep_days));
+        if let Err(err) = removed {
+            error!("Unable to remove old output files: {}", err);
+        }
+    }
+    after_all?;
 
-    Ok(())
+    Ok(finished)
+}
+
+// show what would run, stage by stage, without running anything
+pub fn dry_run(programs: &[Program], mode: &str, settings: &Settings) {
+    let jobs = settings.max_parallel.unwrap_or(programs.len()).max(1);
+    let cwd = env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    println!(
+        "{} {} {}",
+        "DRY RUN".bold(),
+        format!("({})", mode).dimmed(),
+        format!("at most {} programs at the same time", jobs).dimmed()
+    );
+    if mode == "update" {
+        for cmd in &settings.before_all {
+            println!("{} {}", "before_all:".truecolor(F10, F11, F12), cmd);
+        }
+    }
+    for (idx, stage) in stages(programs).iter().enumerate() {
+        println!("{}", format!("Stage {}:", idx + 1).bold().yellow());
+        for arg in stage {
+            let (cmd, before, after) = match mode {
+                "update" => (&arg.update_cmd, &arg.before_update, &arg.after_update),
+                "outdated" => (&arg.outdated_cmd, &Vec::new(), &Vec::new()),
+                _ => (&arg.info_cmd, &Vec::new(), &Vec::new()),
+            };
+            let hooks = |cmds: &Vec<Cmd>| {
+                cmds.iter()
+                    .map(|cmd| cmd.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" && ")
+            };
+            println!(
+                "[ {} ] {}",
+                arg.symbol.dimmed(),
+                arg.name.truecolor(F4, F5, F6).bold()
+            );
+            let details = [
+                (
+                    "command",
+                    match cmd {
+                        Some(cmd) => cmd.to_string(),
+                        None => "none, skipped".to_string(),
+                    },
+                ),
+                (
+                    "version",
+                    match (mode, &arg.version_cmd) {
+                        ("update", Some(cmd)) => cmd.to_string(),
+                        _ => String::new(),
+                    },
+                ),
+                ("pre-hook", hooks(before)),
+                ("post-hook", hooks(after)),
+                ("executer", arg.executer.clone()),
+                (
+                    "cwd",
+                    match &arg.cwd {
+                        Some(dir) => dir.display().to_string(),
+                        None => cwd.clone(),
+                    },
+                ),
+                ("env", env_summary(arg)),
+                (
+                    "output",
+                    match arg.has_output {
+                        true => arg.outputfile.clone(),
+                        false => "not written".to_string(),
+                    },
+                ),
+                (
+                    "timeout",
+                    match arg.timeout {
+                        Some(timeout) => HumanDuration(timeout).to_string(),
+                        None => "none".to_string(),
+                    },
+                ),
+                (
+                    "retries",
+                    match arg.retries {
+                        Some(retries) if retries > 0 => format!(
+                            "{}, the first after {}",
+                            retries,
+                            HumanDuration(arg.retry_delay)
+                        ),
+                        _ => String::new(),
+                    },
+                ),
+                ("after", arg.after.join(", ")),
+            ];
+            for (key, value) in details {
+                if !value.is_empty() {
+                    println!(
+                        "      {:<10} {}",
+                        format!("{}:", key).truecolor(F10, F11, F12),
+                        value
+                    );
+                }
+            }
+        }
+    }
+    if mode == "update" {
+        for cmd in &settings.after_all {
+            println!("{} {}", "after_all:".truecolor(F10, F11, F12), cmd);
+        }
+    }
+}
+
+// group the programs so that every group only depends on the groups before it
+fn stages(programs: &[Program]) -> Vec<Vec<&Program>> {
+    let names: HashSet<_> = programs.iter().map(|arg| arg.name.as_str()).collect();
+    let mut done = HashSet::new();
+    let mut stages = Vec::new();
+
+    loop {
+        let stage: Vec<_> = programs
+            .iter()
+            .filter(|arg| !done.contains(arg.name.as_str()))
+            .filter(|arg| {
+                arg.after
+                    .iter()
+                    .all(|dep| done.contains(dep.as_str()) || !names.contains(dep.as_str()))
+            })
+            .collect();
+        if stage.is_empty() {
+            break;
+        }
+        done.extend(stage.iter().map(|arg| arg.name.as_str()));
+        stages.push(stage);
+    }
+
+    stages
+}
+
+// the inherited environment plus the changes a program makes to it
+fn env_summary(arg: &Program) -> String {
+    let mut summary = vec!["inherited".to_string()];
+    summary.extend(
+        arg.env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value)),
+    );
+    summary.extend(arg.env_remove.iter().map(|key| format!("-{}", key)));
+    summary.join(" ")
+}
+
+pub fn any_failed(programs: &[Program]) -> bool {
+    programs
+        .iter()
+        .any(|program| matches!(program.state, State::Failed | State::TimedOut))
+}
+
+// with an indicator that streams, every line of the output is passed on as soon as it arrives
+fn run_cmd(
+    mut command: Command,
+    timeout: Option<Duration>,
+    live: Option<&Indicator>,
+) -> io::Result<(Output, bool)> {
+    // stdout and stderr are piped back to us, nothing can prompt for input
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // a separate process group lets us kill the whole tree once the timeout expires
+    #[cfg(unix)]
+    if timeout.is_some() {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    let live = live.filter(|indicator| indicator.stream.is_some());
+    let (sender, lines) = mpsc::channel();
+    let (finished, done) = mpsc::channel();
+    let mut child = command.spawn()?;
+    let _running = Running::track(child.id(), timeout.is_some());
+    let stdout = read_pipe(
+        child.stdout.take(),
+        live.map(|_| sender.clone()),
+        finished.clone(),
+    );
+    let stderr = read_pipe(child.stderr.take(), live.map(|_| sender.clone()), finished);
+    drop(sender);
+
+    let poll = Duration::from_millis(100);
+    let started = Instant::now();
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if timeout.is_some_and(|limit| started.elapsed() >= limit) {
+            kill_tree(child.id(), true);
+            let _ = child.kill();
+            timed_out = true;
+            break child.wait()?;
+        }
+        match live {
+            // waiting for the next line doubles as the poll interval
+            Some(indicator) => match lines.recv_timeout(poll) {
+                Ok(line) => indicator.output(&line),
+                Err(RecvTimeoutError::Timeout) => {}
+                // both pipes closed, but the child is still running
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(poll),
+            },
+            None => thread::sleep(poll),
+        }
+    };
+
+    // a daemon started by the program inherits the pipes and may keep them open for good,
+    // its readers are left behind with whatever they haven't read yet
+    let deadline = Instant::now() + PIPE_GRACE;
+    for _ in 0..2 {
+        if done
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .is_err()
+        {
+            break;
+        }
+    }
+    let take = |buf: &Mutex<Vec<u8>>| std::mem::take(&mut *buf.lock().unwrap());
+    let output = Output {
+        status,
+        stdout: take(&stdout),
+        stderr: take(&stderr),
+    };
+    if let Some(indicator) = live {
+        for line in lines.try_iter() {
+            indicator.output(&line);
+        }
+    }
+
+    Ok((output, timed_out))
 }
 
-fn run_cmd(cmd: &str) -> Result<(), Box<dyn Error>> {
+// read a pipe into a buffer shared with run_cmd, which is told once the pipe is closed
+fn read_pipe<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    lines: Option<Sender<String>>,
+    finished: Sender<()>,
+) -> Arc<Mutex<Vec<u8>>> {
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let shared = Arc::clone(&buf);
+    thread::spawn(move || {
+        match (pipe, lines) {
+            (Some(mut pipe), None) => {
+                let mut chunk = [0; 8192];
+                loop {
+                    match pipe.read(&mut chunk) {
+                        Ok(0) => break,
+                        Ok(read) => shared.lock().unwrap().extend_from_slice(&chunk[..read]),
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                        Err(_) => break,
+                    }
+                }
+            }
+            (Some(pipe), Some(lines)) => {
+                let mut reader = BufReader::new(pipe);
+                let mut line = Vec::new();
+                while matches!(reader.read_until(b'\n', &mut line), Ok(read) if read > 0) {
+                    // progress bars redraw with \r, a terminal would only show the last part
+                    let text = String::from_utf8_lossy(&line);
+                    let shown = text
+                        .rsplit('\r')
+                        .map(str::trim_end)
+                        .find(|part| !part.is_empty());
+                    if let Some(shown) = shown {
+                        let _ = lines.send(shown.to_string());
+                    }
+                    shared.lock().unwrap().append(&mut line);
+                }
+            }
+            (None, _) => {}
+        }
+        let _ = finished.send(());
+    });
+
+    buf
+}
+
+// the entry of a child in RUNNING, removed again once run_cmd is done with it
+struct Running(u32);
+
+impl Running {
+    fn track(pid: u32, group: bool) -> Running {
+        RUNNING.lock().unwrap().push((pid, group));
+        Running(pid)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap_or_else(|err| err.into_inner());
+        running.retain(|(pid, _)| *pid != self.0);
+    }
+}
+
+// kill every program that is still running, so nothing is left behind after Ctrl-C
+pub fn kill_running() {
+    let mut running = RUNNING.lock().unwrap_or_else(|err| err.into_inner());
+    for (pid, group) in running.drain(..) {
+        kill_tree(pid, group);
+    }
+}
+
+fn kill_tree(pid: u32, group: bool) {
+    let pid = pid.to_string();
     if cfg!(target_os = "windows") {
-        Command::new("powershell").args(["-c", cmd]).status()?
+        let _ = Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid])
+            .output();
+    } else if group {
+        // the child is the leader of its own process group
+        let _ = Command::new("kill")
+            .args(["-KILL", "--", &format!("-{}", pid)])
+            .output();
     } else {
-        unimplemented!();
+        let _ = Command::new("kill").args(["-KILL", &pid]).output();
+    }
+}
+
+fn run_program(arg: &mut Program, mode: &str, spinner: &Indicator) {
+    let cmd = match mode {
+        "update" => arg.update_cmd.clone(),
+        "info" => arg.info_cmd.clone(),
+        "outdated" => arg.outdated_cmd.clone(),
+        _ => unreachable!(),
+    };
+
+    match cmd {
+        // a failing before_update hook leaves the program failed and skips the update
+        Some(_) if mode == "update" && !run_hooks(arg, "before_update", spinner) => {}
+        Some(cmd) => {
+            if mode == "update" {
+                arg.version_before = check_version(arg);
+            }
+            let max_attempts = arg.retries.unwrap_or(0) + 1;
+            let msg_len = arg.msg.len();
+            for attempt in 1..=max_attempts {
+                if attempt > 1 {
+                    // wait twice as long before every further retry, but not forever
+                    let limit = arg.retry_delay.max(Duration::from_secs(MAX_RETRY_DELAY));
+                    let delay = arg
+                        .retry_delay
+                        .checked_mul(2u32.saturating_pow(attempt - 2))
+                        .map_or(limit, |delay| delay.min(limit));
+                    error!(
+                        "{} attempt {}/{} failed, retrying in {}",
+                        arg.name,
+                        attempt - 1,
+                        max_attempts,
+                        HumanDuration(delay)
+                    );
+                    spinner.set_message(format!(
+                        "{} {}",
+                        format!("retrying in {}", HumanDuration(delay)).truecolor(250, 140, 0),
+                        format!("(attempt {}/{})", attempt, max_attempts).dimmed()
+                    ));
+                    thread::sleep(delay);
+                    spinner.set_message(running_message(mode, attempt, max_attempts));
+                    arg.msg.truncate(msg_len);
+                }
+                arg.attempts = attempt;
+
+                match run_cmd(arg.command(&cmd), arg.timeout, Some(spinner)) {
+                    Ok((output, timed_out)) => {
+                        if let Err(err) = arg.capture(&cmd, &output) {
+                            arg.msg
+                                .push(format!("Unable to write output file: {}", err));
+                        }
+                        if timed_out {
+                            arg.state = State::TimedOut;
+                        }
+                        // some tools exit with an error code as soon as anything is outdated (npm, dnf)
+                        // or when nothing is (pacman)
+                        let code = output.status.code();
+                        if mode == "outdated"
+                            && arg.state == State::Failed
+                            && code.is_some_and(|code| arg.outdated_exit_codes.contains(&code))
+                        {
+                            arg.state = State::Success;
+                        }
+                        arg.scan_output();
+                        if mode == "outdated"
+                            && matches!(arg.state, State::Success | State::Warning)
+                        {
+                            arg.count_pending();
+                        }
+                    }
+                    Err(err) => {
+                        arg.state = State::Failed;
+                        arg.msg.push(format!("Unable to run command: {}", err));
+                    }
+                }
+                if !matches!(arg.state, State::Failed | State::TimedOut) {
+                    break;
+                }
+            }
+            if mode == "update" && matches!(arg.state, State::Success | State::Warning) {
+                arg.version_after = check_version(arg);
+                run_hooks(arg, "after_update", spinner);
+            }
+        }
+        None => {
+            arg.state = State::Skipped;
+            match mode {
+                "update" => arg.msg.push("No update command found".to_string()),
+                "outdated" => arg.msg.push("No outdated command found".to_string()),
+                _ => arg.msg.push("No information found".to_string()),
+            }
+        }
+    }
+
+    match arg.state {
+        State::Failed => error!("{} failed: {}", arg.name, failure_reason(arg)),
+        State::TimedOut => error!("{} timed out: {}", arg.name, timeout_reason(arg)),
+        _ => {}
+    }
+}
+
+// run cmd_for_version, a missing version never fails the update
+fn check_version(arg: &mut Program) -> Option<String> {
+    let cmd = arg.version_cmd.clone()?;
+    match run_cmd(arg.command(&cmd), arg.timeout, None) {
+        Ok((output, false)) => {
+            if let Err(err) = arg.write_output(&cmd, &output) {
+                arg.msg
+                    .push(format!("Unable to write output file: {}", err));
+            }
+            arg.parse_version(&output)
+        }
+        _ => None,
+    }
+}
+
+// run the before_update or after_update hooks of a program, stops at the first one that fails
+fn run_hooks(arg: &mut Program, hook: &str, spinner: &Indicator) -> bool {
+    let cmds = match hook {
+        "before_update" => arg.before_update.clone(),
+        _ => arg.after_update.clone(),
     };
 
+    for cmd in cmds {
+        match run_cmd(arg.command(&cmd), arg.timeout, Some(spinner)) {
+            Ok((output, timed_out)) => {
+                if let Err(err) = arg.write_output(&cmd, &output) {
+                    arg.msg
+                        .push(format!("Unable to write output file: {}", err));
+                }
+                if timed_out {
+                    arg.state = State::TimedOut;
+                    arg.msg.push(format!("{} hook timed out: {}", hook, cmd));
+                    return false;
+                }
+                if !output.status.success() {
+                    arg.status = Some(output.status);
+                    arg.state = State::Failed;
+                    arg.msg.push(format!("{} hook failed: {}", hook, cmd));
+                    return false;
+                }
+            }
+            Err(err) => {
+                arg.state = State::Failed;
+                arg.msg
+                    .push(format!("Unable to run {} hook: {}", hook, err));
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+// run the before_all or after_all hooks of the config, a failing one ends the run
+fn run_global_hooks(
+    cmds: &[Cmd],
+    hook: &str,
+    format: Format,
+    plain: bool,
+) -> Result<(), Box<dyn Error>> {
+    for cmd in cmds {
+        if format.is_text() {
+            print_event(
+                &format!("{} {} {}", "↪".dimmed(), hook.truecolor(F10, F11, F12), cmd),
+                plain,
+            );
+        }
+        let (output, _) = run_cmd(cmd.command(), None, None)
+            .map_err(|err| format!("Unable to run {} hook {}: {}", hook, cmd, err))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!(
+                "{} hook failed ({}): {} {}",
+                hook,
+                match output.status.code() {
+                    Some(code) => format!("exit {}", code),
+                    None => "terminated".to_string(),
+                },
+                cmd,
+                stderr.trim()
+            )
+            .trim_end()
+            .into());
+        }
+    }
+
     Ok(())
 }
 
+fn failure_reason(arg: &Program) -> String {
+    match arg.status.map(|status| status.code()) {
+        Some(Some(0)) if !arg.matches.is_empty() => "error in output".to_string(),
+        Some(Some(code)) => format!("exit {}", code),
+        Some(None) => "terminated".to_string(),
+        None => arg.msg.join(" "),
+    }
+}
+
+fn timeout_reason(arg: &Program) -> String {
+    match arg.timeout {
+        Some(timeout) => format!("killed after {}", HumanDuration(timeout)),
+        None => "killed".to_string(),
+    }
+}
+
+// the combined report of "up outdated"
+fn print_outdated(finished: &[Program], plain: bool) {
+    print_event(&"Pending updates:".bold().yellow().to_string(), plain);
+    for arg in finished {
+        let pending = match (arg.state, arg.pending) {
+            (_, Some(0)) => "up to date".truecolor(59, 179, 140).to_string(),
+            (_, Some(pending)) => pending
+                .to_string()
+                .truecolor(F10, F11, F12)
+                .bold()
+                .to_string(),
+            (State::Skipped, None) => "unknown, no cmd_for_outdated".dimmed().to_string(),
+            _ => "unknown, the check failed"
+                .truecolor(250, 0, 104)
+                .to_string(),
+        };
+        print_event(
+            &format!("  {}{} {}", arg.name.bold(), arg.placeholder, pending),
+            plain,
+        );
+    }
+
+    let outdated: Vec<_> = finished
+        .iter()
+        .filter(|arg| arg.pending.is_some_and(|pending| pending > 0))
+        .collect();
+    let total: usize = outdated.iter().filter_map(|arg| arg.pending).sum();
+    match outdated.len() {
+        0 => print_event(
+            &format!("✔ {}", "everything is up to date".truecolor(59, 179, 140)),
+            plain,
+        ),
+        _ => print_event(
+            &format!(
+                "⇡ {}",
+                format!(
+                    "{} pending updates in {} of {} programs",
+                    total,
+                    outdated.len(),
+                    finished.len()
+                )
+                .truecolor(F10, F11, F12)
+            ),
+            plain,
+        ),
+    }
+}
+
+// quote the lines that matched the error or warning patterns
+fn print_matches(finished: &[Program], plain: bool) {
+    const MAX_LINES: usize = 5;
+
+    for arg in finished.iter().filter(|arg| !arg.matches.is_empty()) {
+        let heading = match arg.state {
+            State::Warning => format!("⚠ {} completed with warnings:", arg.name)
+                .truecolor(250, 140, 0)
+                .to_string(),
+            _ => format!("✘ {} has errors in the output:", arg.name)
+                .truecolor(250, 0, 104)
+                .to_string(),
+        };
+        print_event(&heading, plain);
+        for line in arg.matches.iter().take(MAX_LINES) {
+            print_event(&format!("    {} {}", ">".dimmed(), line), plain);
+        }
+        if arg.matches.len() > MAX_LINES {
+            let more = arg.matches.len() - MAX_LINES;
+            print_event(
+                &format!(
+                    "    {}",
+                    format!("... {} more, see {}", more, arg.outputfile).dimmed()
+                ),
+                plain,
+            );
+        }
+    }
+}
+
+fn running_message(mode: &str, attempt: u32, max_attempts: u32) -> String {
+    let running = match mode {
+        "update" => "updating",
+        "outdated" => "checking",
+        _ => "collecting info",
+    };
+    match attempt {
+        1 => format!("{}", running.truecolor(250, 0, 104)),
+        _ => format!(
+            "{} {}",
+            running.truecolor(250, 0, 104),
+            format!("(attempt {}/{})", attempt, max_attempts).dimmed()
+        ),
+    }
+}
+
+fn finish_message(arg: &Program) -> String {
+    let state = match arg.state {
+        State::Success => match arg.pending {
+            Some(0) => format!("{}", "up to date".truecolor(59, 179, 140)),
+            Some(pending) => format!("{} pending", pending)
+                .truecolor(F10, F11, F12)
+                .to_string(),
+            None => match (&arg.version_before, &arg.version_after) {
+                (Some(before), Some(after)) if before != after => {
+                    format!(
+                        "{}",
+                        format!("{} → {}", before, after).truecolor(59, 179, 140)
+                    )
+                }
+                (_, Some(version)) => format!(
+                    "{} {}",
+                    "done".truecolor(59, 179, 140),
+                    format!("({})", version).dimmed()
+                ),
+                _ => format!("{}", "done".truecolor(59, 179, 140)),
+            },
+        },
+        State::Failed => match arg.status {
+            Some(_) => format!("failed ({})", failure_reason(arg)),
+            None => "failed".to_string(),
+        }
+        .truecolor(250, 0, 104)
+        .to_string(),
+        State::TimedOut => format!("timed out ({})", timeout_reason(arg))
+            .truecolor(250, 140, 0)
+            .to_string(),
+        State::Skipped => format!("{}", "skipped".truecolor(F10, F11, F12)),
+        State::Pending => format!("{}", "pending".dimmed()),
+        State::Warning => format!("{}", "completed with warnings".truecolor(250, 140, 0)),
+    };
+    let state = match arg.attempts {
+        0 | 1 => state,
+        attempts => format!("{} {}", state, format!("({} attempts)", attempts).dimmed()),
+    };
+
+    match arg.msg.is_empty() {
+        true => state,
+        false => format!("{}    \t|  {}", state, arg.msg.join(" ")),
+    }
+}
+
+// the spinner of one program, or its timestamped lines in plain mode
+struct Indicator {
+    spinner: ProgressBar,
+    prefix: String,
+    plain: bool,
+    // "[name]" in the color of the program, in front of its streamed lines
+    label: String,
+    stream: Option<Stream>,
+    format: Format,
+    // the last message, the spinner stream shows the latest line next to it
+    message: RefCell<String>,
+}
+
+impl Indicator {
+    fn set_message(&self, msg: String) {
+        self.print(&msg);
+        self.message.replace(msg.clone());
+        self.spinner.set_message(msg);
+    }
+
+    // one line of output of the running program
+    fn output(&self, line: &str) {
+        match self.stream {
+            Some(Stream::Spinner) if !self.plain => {
+                self.spinner
+                    .set_message(format!("{} {}", self.message.borrow(), line.dimmed()))
+            }
+            Some(_) if self.plain || self.spinner.is_hidden() => {
+                print_text(&format!("{} {}", self.label, line), true);
+            }
+            Some(_) => self.spinner.println(format!("{} {}", self.label, line)),
+            None => {}
+        }
+    }
+
+    // every finished program ends here, whether it ran or was skipped
+    fn finish(&self, arg: &Program) {
+        let msg = finish_message(arg);
+        self.print(&msg);
+        self.spinner.finish_with_message(msg);
+        if self.format == Format::Jsonl {
+            print_value(&ProgramRun::from(arg), self.format);
+        }
+    }
+
+    fn print(&self, msg: &str) {
+        if self.plain {
+            print_event(&format!("{} {}", self.prefix, msg), self.plain);
+        }
+    }
+}
+
+// a line about the run, timestamped in plain mode like the state changes of the programs
+fn print_event(line: &str, plain: bool) {
+    match plain {
+        true => print_text(
+            &format!("{} {}", Local::now().format("%Y-%m-%d %H:%M:%S"), line).replace('\t', " "),
+            plain,
+        ),
+        false => println!("{}", line),
+    }
+}
+
+type Job = (usize, Program, Indicator);
+
+struct Schedule {
+    ready: VecDeque<Job>,
+    waiting: Vec<Job>,
+    // finished programs, true if their dependents are allowed to run
+    finished: HashMap<String, bool>,
+    running: usize,
+    results: Vec<(usize, Program)>,
+}
+
+impl Schedule {
+    // move every waiting program whose dependencies are all finished to the ready queue,
+    // or skip it if one of them didn't succeed
+    fn release(&mut self, pb: &ProgressBar) {
+        loop {
+            let mut changed = false;
+            for job in std::mem::take(&mut self.waiting) {
+                if !job
+                    .1
+                    .after
+                    .iter()
+                    .all(|dep| self.finished.contains_key(dep))
+                {
+                    self.waiting.push(job);
+                    continue;
+                }
+
+                changed = true;
+                let blocked: Vec<_> = job
+                    .1
+                    .after
+                    .iter()
+                    .filter(|dep| !self.finished[*dep])
+                    .cloned()
+                    .collect();
+                if blocked.is_empty() {
+                    self.ready.push_back(job);
+                } else {
+                    let (idx, mut arg, spinner) = job;
+                    arg.state = State::Skipped;
+                    arg.msg
+                        .push(format!("Dependency failed: {}", blocked.join(", ")));
+                    spinner.finish(&arg);
+                    pb.inc(1);
+                    self.finished.insert(arg.name.clone(), false);
+                    self.results.push((idx, arg));
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
 fn progress_bar(
     commands: Vec<Program>,
     num: u64,
     mode: &str,
-) -> Result<Arc<MultiProgress>, Box<dyn Error>> {
+    jobs: usize,
+    format: Format,
+    plain: bool,
+    stream: Option<Stream>,
+) -> Result<Vec<Program>, Box<dyn Error>> {
     let started = Instant::now();
     let spinner_style = ProgressStyle::with_template("{prefix} {spinner:.red} {wide_msg}").unwrap();
     // .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ");
 
     let m = Arc::new(MultiProgress::new());
+    // the spinners would end up between the json or plain lines
+    if !format.is_text() || plain {
+        m.set_draw_target(ProgressDrawTarget::hidden());
+    }
     let sty = ProgressStyle::with_template(
         "{spinner:.red} [{elapsed_precise}] {bar:40.white/white} {pos:>3}/{len:2} {percent}% {msg:>5}",
     )
@@ -100,12 +980,16 @@ fn progress_bar(
     pb.set_style(sty);
 
     pb.tick();
-    let handles: Vec<_> = commands
+    // every program waits as "queued" until its dependencies are done
+    // and one of the workers picks it up
+    let names: HashSet<_> = commands.iter().map(|arg| arg.name.clone()).collect();
+    let waiting: Vec<_> = commands
         .into_iter()
-        .map(|mut arg| {
-            let pb = pb.clone();
+        .enumerate()
+        .map(|(idx, mut arg)| {
+            // dependencies that are not part of this run don't hold anything back
+            arg.after.retain(|dep| names.contains(dep));
             let spinner = m.add(ProgressBar::new_spinner());
-            spinner.enable_steady_tick(Duration::from_millis(200));
             spinner.set_style(spinner_style.clone());
             spinner.set_prefix(format!(
                 "[ {} ] {}{}",
@@ -114,85 +998,154 @@ fn progress_bar(
                 // arg.name.truecolor(127, 111, 219).dimmed(),
                 arg.placeholder
             ));
-            match mode {
-                "update" => thread::spawn(move || {
-                    spinner.set_message(format!("{}", "updating".truecolor(250, 0, 104),));
-                    spinner.tick();
-                    match arg.update_cmd {
-                        Some(cmd) => {
-                            run_cmd(cmd.as_str()).unwrap();
-                        }
-                        None => {
-                            arg.msg.push("No update command found".to_string());
-                        }
-                    }
-                    spinner.finish_with_message(match arg.msg.is_empty() {
-                        true => {
-                            format!("{}", "done".truecolor(59, 179, 140))
-                        }
-                        false => {
-                            format!(
-                                "{}    \t|  {}",
-                                "done".truecolor(59, 179, 140),
-                                arg.msg.join(" "),
-                            )
-                        }
-                    });
-                    pb.inc(1);
-                }),
-                "info" => thread::spawn(move || {
-                    spinner.set_message(format!("{}", "collecting info".truecolor(250, 0, 104),));
-                    spinner.tick();
-                    match arg.info_cmd {
-                        Some(cmd) => {
-                            run_cmd(cmd.as_str()).unwrap();
+            // the program colors take turns, so that neighbouring programs can be told apart
+            let (r, g, b) = [(F4, F5, F6), (F10, F11, F12), (250, 140, 0)][idx % 3];
+            let spinner = Indicator {
+                prefix: format!("[ {} ] {}{}", arg.symbol, arg.name, arg.placeholder),
+                // json has its own lines
+                plain: plain && format.is_text(),
+                label: format!("[{}]", arg.name).truecolor(r, g, b).to_string(),
+                stream: stream.filter(|_| format.is_text()),
+                format,
+                message: RefCell::new(String::new()),
+                spinner,
+            };
+            match arg.after.is_empty() {
+                true => spinner.set_message(format!("{}", "queued".dimmed())),
+                false => spinner.set_message(format!(
+                    "{}",
+                    format!("waiting for {}", arg.after.join(", ")).dimmed()
+                )),
+            }
+            spinner.spinner.tick();
+            (idx, arg, spinner)
+        })
+        .collect();
+
+    let mut schedule = Schedule {
+        ready: VecDeque::new(),
+        waiting,
+        finished: HashMap::new(),
+        running: 0,
+        results: Vec::new(),
+    };
+    schedule.release(&pb);
+    let schedule = Arc::new((Mutex::new(schedule), Condvar::new()));
+
+    let workers: Vec<_> = (0..jobs.max(1))
+        .map(|_| {
+            let schedule = Arc::clone(&schedule);
+            let pb = pb.clone();
+            let mode = mode.to_string();
+            thread::spawn(move || loop {
+                let (lock, cvar) = &*schedule;
+                let next = {
+                    let mut schedule = lock.lock().unwrap();
+                    loop {
+                        if let Some(job) = schedule.ready.pop_front() {
+                            schedule.running += 1;
+                            break Some(job);
                         }
-                        None => {
-                            arg.msg.push("No information found".to_string());
+                        // nothing ready and nothing running means nothing can become ready
+                        if schedule.running == 0 {
+                            break None;
                         }
+                        schedule = cvar.wait(schedule).unwrap();
                     }
-                    spinner.finish_with_message(match arg.msg.is_empty() {
-                        true => {
-                            format!("{}", "done".truecolor(59, 179, 140))
-                        }
-                        false => {
-                            format!(
-                                "{}    \t|  {}",
-                                "done".truecolor(59, 179, 140),
-                                arg.msg.join(" "),
-                            )
-                        }
-                    });
-                    pb.inc(1);
-                }),
-                _ => {
-                    unreachable!()
+                };
+                let Some((idx, mut arg, spinner)) = next else {
+                    cvar.notify_all();
+                    break;
+                };
+
+                spinner
+                    .spinner
+                    .enable_steady_tick(Duration::from_millis(200));
+                spinner.set_message(running_message(&mode, 1, 1));
+                spinner.spinner.tick();
+                let started = Instant::now();
+                // a panicking program must not take the worker and its queue down with it
+                if panic::catch_unwind(AssertUnwindSafe(|| run_program(&mut arg, &mode, &spinner)))
+                    .is_err()
+                {
+                    arg.state = State::Failed;
+                    arg.msg.push("Worker thread panicked".to_string());
+                    error!("{} failed: worker thread panicked", arg.name);
                 }
-            }
+                arg.duration = Some(started.elapsed());
+                spinner.finish(&arg);
+                pb.inc(1);
+
+                let mut schedule = lock.lock().unwrap();
+                schedule.running -= 1;
+                // a program without a command doesn't hold back its dependents
+                let satisfied =
+                    matches!(arg.state, State::Success | State::Warning | State::Skipped);
+                schedule.finished.insert(arg.name.clone(), satisfied);
+                schedule.results.push((idx, arg));
+                schedule.release(&pb);
+                cvar.notify_all();
+            })
         })
         .collect();
 
-    for h in handles {
-        let _ = h.join();
+    for worker in workers {
+        let _ = worker.join();
     }
 
+    let mut finished = std::mem::take(&mut schedule.0.lock().unwrap().results);
+    finished.sort_by_key(|(idx, _)| *idx);
+    let finished: Vec<_> = finished.into_iter().map(|(_, arg)| arg).collect();
+
     pb.finish_with_message(format!("{}", "done".bold().truecolor(59, 179, 140)));
 
     // m.clear().unwrap();
 
-    println!(
-        "{} {} {}",
-        "✔",
-        "all done in".truecolor(59, 179, 140),
-        HumanDuration(started.elapsed())
-            .to_string()
-            .truecolor(127, 111, 219)
-    );
+    if !format.is_text() {
+        return Ok(finished);
+    }
+
+    let failed: Vec<_> = finished
+        .iter()
+        .filter_map(|arg| match arg.state {
+            State::Failed => Some(arg.name.clone()),
+            State::TimedOut => Some(format!("{} (timed out)", arg.name)),
+            _ => None,
+        })
+        .collect();
+    let summary = match failed.is_empty() {
+        true => format!(
+            "✔ {} {}",
+            "all done in".truecolor(59, 179, 140),
+            HumanDuration(started.elapsed())
+                .to_string()
+                .truecolor(127, 111, 219)
+        ),
+        false => format!(
+            "✘ {} {} {}",
+            format!("{} failed after", failed.len()).truecolor(250, 0, 104),
+            HumanDuration(started.elapsed())
+                .to_string()
+                .truecolor(127, 111, 219),
+            format!("[{}]", failed.join(", ")).dimmed()
+        ),
+    };
+    print_event(&summary, plain);
+    print_matches(&finished, plain);
 
-    Ok(m)
+    Ok(finished)
 }
 
-pub fn get_sys() {
+#[derive(Serialize)]
+struct SysInfo {
+    name: Option<String>,
+    kernel_version: Option<String>,
+    os_version: Option<String>,
+    host_name: Option<String>,
+    cpus: usize,
+}
+
+pub fn get_sys(format: Format) {
     let mut sys = System::new_all();
 
     // First we update all information of our `System` struct.
@@ -202,6 +1155,18 @@ pub fn get_sys() {
             .with_users_list(),
     );
 
+    if !format.is_text() {
+        let info = SysInfo {
+            name: sys.name(),
+            kernel_version: sys.kernel_version(),
+            os_version: sys.os_version(),
+            host_name: sys.host_name(),
+            cpus: sys.cpus().len(),
+        };
+        print_value(&info, format);
+        return;
+    }
+
     // Display system information:
     println!(
         "{}             {}",
@@ -232,14 +1197,26 @@ pub fn get_sys() {
     );
 }
 
-pub fn confirm(msg: &str) -> bool {
+pub fn confirm(msg: &str, yes: bool) -> bool {
     loop {
         println!("{}", msg);
+        if yes {
+            println!("y {}", "(--yes)".dimmed());
+            return true;
+        }
 
         let mut input = String::new();
-        io::stdin()
+        let read = io::stdin()
             .read_line(&mut input)
             .expect("Failed to read input");
+        // nobody is going to answer (cron, ci, closed stdin)
+        if read == 0 {
+            println!(
+                "{}",
+                "No answer, use --yes to confirm without a terminal".dimmed()
+            );
+            return false;
+        }
 
         match input.trim().to_lowercase().as_str() {
             "yes" | "y" => return true,
@@ -249,7 +1226,80 @@ pub fn confirm(msg: &str) -> bool {
     }
 }
 
-pub fn list_programs(programs: &Vec<Program>) {
+// ask for a value, an empty input keeps the default
+pub fn prompt(msg: &str, default: &str) -> String {
+    println!("{} [{}]:", msg, default.dimmed());
+
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read input");
+
+    match input.trim() {
+        "" => default.to_string(),
+        input => input.to_string(),
+    }
+}
+
+pub fn list_presets() {
+    println!("{}", "Available presets:".bold().yellow());
+    for preset in presets() {
+        println!(
+            "{:<8} {} {} {}",
+            preset.name.truecolor(F4, F5, F6).bold(),
+            "update:".dimmed(),
+            preset.executer,
+            preset.cmd_for_update,
+        );
+        println!(
+            "{:<8} {} {} {}",
+            "",
+            "info:  ".dimmed(),
+            preset.executer,
+            preset.cmd_for_info,
+        );
+        if let Some(cmd) = preset.cmd_for_outdated {
+            println!(
+                "{:<8} {} {} {}",
+                "",
+                "outdated:".dimmed(),
+                preset.executer,
+                cmd
+            );
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProgramEntry {
+    name: String,
+    symbol: String,
+    executer: String,
+    update: Option<String>,
+    info: Option<String>,
+    outdated: Option<String>,
+    version: Option<String>,
+    after: Vec<String>,
+}
+
+pub fn list_programs(programs: &Vec<Program>, format: Format) {
+    if !format.is_text() {
+        let entries: Vec<_> = programs
+            .iter()
+            .map(|program| ProgramEntry {
+                name: program.name.clone(),
+                symbol: program.symbol.clone(),
+                executer: program.executer.clone(),
+                update: program.update_cmd.as_ref().map(|cmd| cmd.to_string()),
+                info: program.info_cmd.as_ref().map(|cmd| cmd.to_string()),
+                outdated: program.outdated_cmd.as_ref().map(|cmd| cmd.to_string()),
+                version: program.version_cmd.as_ref().map(|cmd| cmd.to_string()),
+                after: program.after.clone(),
+            })
+            .collect();
+        print_entries(&entries, format);
+        return;
+    }
     println!("{}", "Available programs:".bold().yellow());
     for program in programs {
         println!(
@@ -260,8 +1310,36 @@ pub fn list_programs(programs: &Vec<Program>) {
     }
 }
 
-// FIXME
-// pub fn exclude(programs: &Vec<Program>) -> io::Result<Vec<Program>> {
-//     let mut filtered = Vec::new();
-//     Ok(filtered)
-// }
+// keep only the programs in "only" (all if empty) that are not in "skip"
+pub fn filter_programs(
+    programs: Vec<Program>,
+    only: &[String],
+    skip: &[String],
+) -> Result<Vec<Program>, Box<dyn Error>> {
+    let unknown: Vec<_> = only
+        .iter()
+        .chain(skip)
+        .filter(|name| !programs.iter().any(|program| &program.name == *name))
+        .map(|name| name.as_str())
+        .collect();
+    if !unknown.is_empty() {
+        let valid: Vec<_> = programs
+            .iter()
+            .map(|program| program.name.as_str())
+            .collect();
+        return Err(format!(
+            "Unknown program(s): {}\nAvailable programs: {}",
+            unknown.join(", "),
+            valid.join(", ")
+        )
+        .into());
+    }
+
+    let filtered = programs
+        .into_iter()
+        .filter(|program| only.is_empty() || only.contains(&program.name))
+        .filter(|program| !skip.contains(&program.name))
+        .collect();
+
+    Ok(filtered)
+}
@@ -0,0 +1,597 @@
+use crate::commands::prompt;
+use crate::presets::detect_presets;
+use crate::programs::{check_config, config_template, App};
+
+use clap::ArgMatches;
+use owo_colors::colored::*;
+use ron::ser::PrettyConfig;
+
+use std::{fs, io, ops::Range, path::Path};
+
+// where the "apps" list and every entry in it are located in the config file
+struct Layout {
+    // position of the closing "]" of the "apps" list
+    list_end: usize,
+    entries: Vec<Range<usize>>,
+}
+
+// walk through the raw config text, skipping strings and comments,
+// so that entries can be changed without touching anything else in the file
+fn layout(content: &str) -> io::Result<Layout> {
+    let bytes = content.as_bytes();
+    let mut depth = 0;
+    let mut word = String::new();
+    let mut last_word = String::new();
+    let mut in_apps = false;
+    let mut entry_start = None;
+    let mut entries = Vec::new();
+
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let c = bytes[idx];
+
+        if c.is_ascii_alphanumeric() || c == b'_' {
+            if in_apps && depth == 2 && entry_start.is_none() {
+                entry_start = Some(idx);
+            }
+            if depth == 1 {
+                word.push(c as char);
+            }
+            idx += 1;
+            continue;
+        } else if !word.is_empty() {
+            last_word = std::mem::take(&mut word);
+        }
+
+        match c {
+            b'/' if bytes.get(idx + 1) == Some(&b'/') => {
+                while idx < bytes.len() && bytes[idx] != b'\n' {
+                    idx += 1;
+                }
+            }
+            b'/' if bytes.get(idx + 1) == Some(&b'*') => {
+                idx += 2;
+                while idx + 1 < bytes.len() && &bytes[idx..idx + 2] != b"*/" {
+                    idx += 1;
+                }
+                idx += 1;
+            }
+            b'"' => {
+                idx += 1;
+                while idx < bytes.len() && bytes[idx] != b'"' {
+                    if bytes[idx] == b'\\' {
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+            }
+            b'(' | b'[' | b'{' => {
+                depth += 1;
+                if c == b'[' && depth == 2 && last_word == "apps" {
+                    in_apps = true;
+                } else if in_apps && depth == 3 && entry_start.is_none() {
+                    entry_start = Some(idx);
+                }
+            }
+            b')' | b']' | b'}' => {
+                if in_apps && depth == 3 && c == b')' {
+                    if let Some(start) = entry_start.take() {
+                        entries.push(start..idx + 1);
+                    }
+                }
+                if in_apps && depth == 2 && c == b']' {
+                    return Ok(Layout {
+                        list_end: idx,
+                        entries,
+                    });
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Unable to find the \"apps\" list in the config file",
+    ))
+}
+
+// find the entry of the given program and parse it
+fn find_entry(content: &str, name: &str) -> io::Result<(Range<usize>, App)> {
+    for entry in layout(content)?.entries {
+        if let Ok(app) = ron::de::from_str::<App>(&content[entry.clone()]) {
+            // entries with a preset may get their name from it
+            let found = app.clone().expanded().map(|expanded| expanded.name);
+            if found.is_ok_and(|found| found == name) {
+                return Ok((entry, app));
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No program named {} in the config file", name),
+    ))
+}
+
+fn line_start(content: &str, pos: usize) -> usize {
+    content[..pos].rfind('\n').map_or(0, |nl| nl + 1)
+}
+
+fn indentation(content: &str, pos: usize) -> &str {
+    let start = line_start(content, pos);
+    let line = &content[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+fn serialize(app: &App, indent: &str) -> io::Result<String> {
+    let pretty = PrettyConfig::new()
+        .struct_names(true)
+        .compact_arrays(true)
+        .indentor("    ".to_string());
+    let serialized = ron::ser::to_string_pretty(app, pretty)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+    Ok(serialized.replace('\n', &format!("\n{}", indent)))
+}
+
+// check the new content before it replaces the config file and keep a backup of the old one
+pub fn write_config(path: &Path, content: &str) -> io::Result<()> {
+    check_config(content)?;
+
+    let backup = path.with_extension("ron.bak");
+    let tmp = path.with_extension("ron.tmp");
+    if path.exists() {
+        fs::copy(path, &backup)?;
+    }
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+pub fn add_app(path: &Path, app: &App) -> io::Result<()> {
+    let content = fs::read_to_string(path)?;
+    write_config(path, &with_app(&content, app)?)
+}
+
+// the config text with the app appended to the "apps" list
+fn with_app(content: &str, app: &App) -> io::Result<String> {
+    let name = app.clone().expanded()?.name;
+    if find_entry(content, &name).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("A program named {} already exists", name),
+        ));
+    }
+
+    let layout = layout(content)?;
+    let mut new_content = content.to_string();
+
+    // the "]" either sits on its own line or directly behind the last entry
+    let closing_own_line = content[line_start(content, layout.list_end)..layout.list_end]
+        .trim()
+        .is_empty();
+    let (insert_at, indent, prefix) = match layout.entries.last() {
+        Some(last) => {
+            let indent = indentation(content, last.start).to_string();
+            let between = &content[last.end..layout.list_end];
+            if !between.trim_start().starts_with(',') {
+                new_content.insert(last.end, ',');
+            }
+            let shift = new_content.len() - content.len();
+            match closing_own_line {
+                true => (line_start(content, layout.list_end) + shift, indent, ""),
+                false => (layout.list_end + shift, indent, "\n"),
+            }
+        }
+        None => {
+            let indent = format!("{}    ", indentation(content, layout.list_end));
+            match closing_own_line {
+                true => (line_start(content, layout.list_end), indent, ""),
+                false => (layout.list_end, indent, "\n"),
+            }
+        }
+    };
+
+    let entry = format!("{}{}{},\n", prefix, indent, serialize(app, &indent)?);
+    new_content.insert_str(insert_at, &entry);
+
+    Ok(new_content)
+}
+
+pub fn remove_app(path: &Path, name: &str) -> io::Result<()> {
+    let content = fs::read_to_string(path)?;
+    write_config(path, &without_app(&content, name)?)
+}
+
+// the config text without the entry of the program
+fn without_app(content: &str, name: &str) -> io::Result<String> {
+    let (entry, _) = find_entry(content, name)?;
+
+    // take the indentation, the trailing comma and the rest of the line with it
+    let mut start = entry.start;
+    if content[line_start(content, start)..start].trim().is_empty() {
+        start = line_start(content, start);
+    }
+    let rest = &content[entry.end..];
+    let mut end = entry.end + (rest.len() - rest.trim_start_matches([' ', '\t']).len());
+    if content[end..].starts_with(',') {
+        end += 1;
+    }
+    let rest = &content[end..];
+    end += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    // a comment behind the entry belongs to it
+    if content[end..].starts_with("//") {
+        end += content[end..].find('\n').unwrap_or(content.len() - end);
+    }
+    if content[end..].starts_with('\n') && start == line_start(content, start) {
+        end += 1;
+    }
+
+    let mut new_content = content.to_string();
+    new_content.replace_range(start..end, "");
+
+    Ok(new_content)
+}
+
+pub fn get_app(path: &Path, name: &str) -> io::Result<App> {
+    let content = fs::read_to_string(path)?;
+    let (_, app) = find_entry(&content, name)?;
+
+    Ok(app)
+}
+
+// comments inside the edited entry get lost, everything else stays as it is
+pub fn replace_app(path: &Path, name: &str, app: &App) -> io::Result<()> {
+    let content = fs::read_to_string(path)?;
+    write_config(path, &with_replaced_app(&content, name, app)?)
+}
+
+// the config text with the entry of the program replaced by the app
+fn with_replaced_app(content: &str, name: &str, app: &App) -> io::Result<String> {
+    let (entry, _) = find_entry(content, name)?;
+    let indent = indentation(content, entry.start).to_string();
+
+    let mut new_content = content.to_string();
+    new_content.replace_range(entry, &serialize(app, &indent)?);
+
+    Ok(new_content)
+}
+
+// build an app from the command line flags, on top of an existing one when editing
+pub fn app_from_args(sub_match: &ArgMatches, mut app: App) -> App {
+    if let Some(preset) = sub_match.get_one::<String>("preset") {
+        app.preset = Some(preset.to_string());
+    }
+    if let Some(name) = sub_match.get_one::<String>("name") {
+        app.name = name.to_string();
+    }
+    if let Some(symbol) = sub_match.get_one::<String>("symbol") {
+        app.symbol = Some(symbol.to_string());
+    }
+    if let Some(executer) = sub_match.get_one::<String>("executer") {
+        app.executer = executer.to_string();
+    }
+    if let Some(start_extern) = sub_match.get_one::<bool>("start-extern") {
+        app.start_extern = *start_extern;
+    }
+    if let Some(has_output) = sub_match.get_one::<bool>("has-output") {
+        app.has_output = *has_output;
+    }
+    if let Some(cmd) = sub_match.get_one::<String>("update") {
+        app.cmd_for_update = Some(cmd.to_string());
+    }
+    if let Some(cmd) = sub_match.get_one::<String>("info") {
+        app.cmd_for_info = Some(cmd.to_string());
+    }
+    if let Some(timeout) = sub_match.get_one::<u64>("timeout") {
+        app.timeout = Some(*timeout);
+    }
+    if let Some(retries) = sub_match.get_one::<u32>("retries") {
+        app.retries = Some(*retries);
+    }
+    if let Some(after) = sub_match.get_many::<String>("after") {
+        app.after = after.filter(|name| !name.is_empty()).cloned().collect();
+    }
+
+    app
+}
+
+// ask for every field, the current value is kept when the input is empty
+pub fn app_from_prompts(mut app: App) -> App {
+    fn optional(value: &Option<String>) -> String {
+        value.clone().unwrap_or_else(|| "None".to_string())
+    }
+    fn parse_optional(input: String) -> Option<String> {
+        match input.as_str() {
+            "None" | "none" | "-" => None,
+            _ => Some(input),
+        }
+    }
+
+    println!(
+        "{}",
+        "Press enter to keep the value in brackets, enter \"None\" to remove an optional value"
+            .italic()
+            .dimmed()
+    );
+    app.preset = parse_optional(prompt(
+        "preset (see \"up presets\")",
+        &optional(&app.preset),
+    ));
+    app.name = prompt("name", &app.name);
+    app.symbol = parse_optional(prompt("symbol", &optional(&app.symbol)));
+    app.executer = prompt("executer", &app.executer);
+    app.start_extern = confirm_bool("start_extern", app.start_extern);
+    app.has_output = confirm_bool("has_output", app.has_output);
+    app.cmd_for_update = parse_optional(prompt("cmd_for_update", &optional(&app.cmd_for_update)));
+    app.cmd_for_info = parse_optional(prompt("cmd_for_info", &optional(&app.cmd_for_info)));
+    let timeout = app.timeout.map(|t| t.to_string());
+    app.timeout = loop {
+        match parse_optional(prompt("timeout (seconds)", &optional(&timeout))) {
+            Some(input) => match input.parse::<u64>() {
+                Ok(timeout) => break Some(timeout),
+                Err(_) => println!("{}", "Please enter a number or \"None\"".red()),
+            },
+            None => break None,
+        }
+    };
+    let after = prompt("after (comma separated)", &app.after.join(","));
+    app.after = after
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    app
+}
+
+fn confirm_bool(field: &str, default: bool) -> bool {
+    loop {
+        match prompt(field, &default.to_string()).to_lowercase().as_str() {
+            "true" | "yes" | "y" => return true,
+            "false" | "no" | "n" => return false,
+            _ => println!("{}", "Please enter \"true\" or \"false\"".red()),
+        }
+    }
+}
+
+pub fn print_app(app: &App) -> io::Result<()> {
+    println!("{}", serialize(app, "")?);
+
+    Ok(())
+}
+
+// a config file with every installed package manager that has a preset
+pub fn detected_config() -> io::Result<String> {
+    let detected = detect_presets();
+    let names: Vec<_> = detected.iter().map(|preset| preset.name).collect();
+
+    let mut apps = String::new();
+    for preset in &detected {
+        let app = App {
+            preset: Some(preset.name.to_string()),
+            start_extern: true,
+            has_output: true,
+            after: preset
+                .after
+                .iter()
+                .filter(|dep| names.contains(dep))
+                .map(|dep| dep.to_string())
+                .collect(),
+            ..Default::default()
+        };
+        apps.push_str(&format!("        {},\n", serialize(&app, "        ")?));
+    }
+
+    Ok(config_template(&format!(
+        "(\n    apps: [\n{}    ],\n)",
+        apps
+    )))
+}
+
+// line based diff of two texts, every line is prefixed with ' ', '-' or '+'
+pub fn diff(old: &str, new: &str) -> Vec<(char, String)> {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+
+    // longest common subsequence, filled from the end
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push(('+', new[j].to_string()));
+            j += 1;
+        } else {
+            lines.push(('-', old[i].to_string()));
+            i += 1;
+        }
+    }
+
+    lines
+}
+
+pub fn print_diff(lines: &[(char, String)]) {
+    for (sign, line) in lines {
+        match sign {
+            '+' => println!("{}", format!("+ {}", line).green()),
+            '-' => println!("{}", format!("- {}", line).red()),
+            _ => println!("{}", format!("  {}", line).dimmed()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str) -> App {
+        App {
+            name: name.to_string(),
+            executer: "echo".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn names(content: &str) -> Vec<String> {
+        layout(content)
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|entry| ron::de::from_str::<App>(&content[entry]).unwrap().name)
+            .collect()
+    }
+
+    const CONFIG: &str = r#"// apps: [App(name: "commented")]
+(
+    shell: Some("sh"), /* apps: [ */
+    apps: [
+        // the first one
+        App(name: "a", executer: "echo", cmd_for_update: Some("x ) ] \" ("),), // trailing
+        App(name: "b", executer: "echo"),
+        App(name: "c", executer: "echo")
+    ],
+)
+"#;
+
+    #[test]
+    fn layout_skips_comments_and_strings() {
+        let layout = layout(CONFIG).unwrap();
+
+        assert_eq!(layout.entries.len(), 3);
+        for entry in &layout.entries {
+            assert!(CONFIG[entry.clone()].starts_with("App("));
+            assert!(CONFIG[entry.clone()].ends_with(')'));
+        }
+        assert_eq!(&CONFIG[layout.list_end..layout.list_end + 2], "],");
+        assert_eq!(names(CONFIG), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn layout_of_an_empty_list() {
+        let content = "(apps: [])";
+        let layout = layout(content).unwrap();
+
+        assert!(layout.entries.is_empty());
+        assert_eq!(layout.list_end, content.find(']').unwrap());
+    }
+
+    #[test]
+    fn layout_without_apps() {
+        assert!(layout("(shell: None)").is_err());
+        assert!(layout("// apps: []\n(shell: None)").is_err());
+    }
+
+    #[test]
+    fn add_after_an_entry_without_trailing_comma() {
+        let new_content = with_app(CONFIG, &app("d")).unwrap();
+
+        check_config(&new_content).unwrap();
+        assert_eq!(names(&new_content), ["a", "b", "c", "d"]);
+        assert!(new_content.contains("        App(name: \"c\", executer: \"echo\"),\n        App("));
+        assert!(new_content.starts_with("// apps: [App(name: \"commented\")]\n"));
+        assert!(new_content.contains("// trailing\n"));
+    }
+
+    #[test]
+    fn add_to_an_empty_list() {
+        for content in ["(apps: [])", "(\n    apps: [\n    ],\n)"] {
+            let new_content = with_app(content, &app("a")).unwrap();
+
+            check_config(&new_content).unwrap();
+            assert_eq!(names(&new_content), ["a"]);
+        }
+    }
+
+    #[test]
+    fn add_an_existing_name() {
+        assert!(with_app(CONFIG, &app("b")).is_err());
+    }
+
+    #[test]
+    fn remove_takes_the_trailing_comment_along() {
+        let new_content = without_app(CONFIG, "a").unwrap();
+
+        check_config(&new_content).unwrap();
+        assert_eq!(names(&new_content), ["b", "c"]);
+        assert!(!new_content.contains("trailing"));
+        assert!(new_content.contains("        // the first one\n        App(name: \"b\""));
+    }
+
+    #[test]
+    fn remove_the_last_entry_without_trailing_comma() {
+        let new_content = without_app(CONFIG, "c").unwrap();
+
+        check_config(&new_content).unwrap();
+        assert_eq!(names(&new_content), ["a", "b"]);
+        assert!(new_content.contains("App(name: \"b\", executer: \"echo\"),\n    ],"));
+    }
+
+    #[test]
+    fn remove_keeps_everything_else() {
+        let new_content = without_app(CONFIG, "b").unwrap();
+
+        assert_eq!(
+            new_content,
+            CONFIG.replace("        App(name: \"b\", executer: \"echo\"),\n", "")
+        );
+    }
+
+    #[test]
+    fn remove_the_only_entry() {
+        let new_content =
+            without_app("(apps: [App(name: \"a\", executer: \"echo\")])", "a").unwrap();
+
+        assert_eq!(new_content, "(apps: [])");
+        check_config(&new_content).unwrap();
+    }
+
+    #[test]
+    fn remove_an_unknown_name() {
+        assert!(without_app(CONFIG, "commented").is_err());
+    }
+
+    #[test]
+    fn replace_keeps_the_comments_around() {
+        let mut changed = app("a");
+        changed.symbol = Some("Z".to_string());
+        changed.cmd_for_update = Some("y ) (".to_string());
+        let new_content = with_replaced_app(CONFIG, "a", &changed).unwrap();
+
+        check_config(&new_content).unwrap();
+        assert_eq!(names(&new_content), ["a", "b", "c"]);
+        let (_, replaced) = find_entry(&new_content, "a").unwrap();
+        assert_eq!(replaced.symbol.as_deref(), Some("Z"));
+        assert_eq!(replaced.cmd_for_update.as_deref(), Some("y ) ("));
+        assert!(new_content.contains(
+            "        // the first one
+        App("
+        ));
+        assert!(new_content.contains(
+            "), // trailing
+        App(name: \"b\""
+        ));
+    }
+
+    #[test]
+    fn replace_an_unknown_name() {
+        assert!(with_replaced_app(CONFIG, "commented", &app("x")).is_err());
+    }
+}
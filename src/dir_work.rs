@@ -2,15 +2,19 @@ extern crate dirs;
 
 use log::error;
 use owo_colors::colored::*;
+use regex::Regex;
+use serde::Serialize;
 
 use std::{
+    collections::HashMap,
     env, fs, io,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
 pub fn check_create_tmp_dir() -> io::Result<String> {
     let mut tmp_path = env::temp_dir();
-    tmp_path.push("up_tmp\\");
+    tmp_path.push("up_tmp");
 
     if !tmp_path.as_path().exists() {
         fs::create_dir(&tmp_path)?;
@@ -21,22 +25,90 @@ pub fn check_create_tmp_dir() -> io::Result<String> {
     Ok(dir)
 }
 
-pub fn remove_tmps(tmp_dir_path: &str) -> io::Result<()> {
-    for entry in fs::read_dir(tmp_dir_path)? {
-        let entry = entry?;
-        match entry.path().file_name() {
-            Some(file) => {
-                let filename = file.to_string_lossy();
-                if filename.contains(&"up_output_".to_string()) {
-                    fs::remove_file(entry.path())?;
-                    println!("{} {:?}", "Removed:".red(), filename);
-                }
+// remove the output files, only those of the given programs and older than the given age if set
+pub fn remove_tmps(
+    tmp_dir_path: &str,
+    programs: &[String],
+    older_than: Option<Duration>,
+) -> io::Result<usize> {
+    let mut removed = 0;
+    for (name, path, modified) in output_files(tmp_dir_path)? {
+        if !programs.is_empty() && !programs.contains(&name) {
+            continue;
+        }
+        if older_than.is_some_and(|age| !is_older(modified, age)) {
+            continue;
+        }
+        fs::remove_file(&path)?;
+        removed += 1;
+        if let Some(file) = path.file_name() {
+            println!("{} {:?}", "Removed:".red(), file.to_string_lossy());
+        }
+    }
+
+    Ok(removed)
+}
+
+// quietly remove every output file beyond the newest "keep_runs" per program or older than "keep_days"
+pub fn apply_retention(
+    tmp_dir_path: &str,
+    keep_runs: Option<usize>,
+    keep_days: Option<u64>,
+) -> io::Result<usize> {
+    let mut per_program: HashMap<String, Vec<(PathBuf, SystemTime)>> = HashMap::new();
+    for (name, path, modified) in output_files(tmp_dir_path)? {
+        per_program.entry(name).or_default().push((path, modified));
+    }
+
+    // so many days that they don't fit into a duration are never reached
+    let max_age = keep_days
+        .and_then(|days| days.checked_mul(24 * 60 * 60))
+        .map(Duration::from_secs);
+    let mut removed = 0;
+    for files in per_program.values_mut() {
+        // the newest first
+        files.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+        for (idx, (path, modified)) in files.iter().enumerate() {
+            let too_many = keep_runs.is_some_and(|keep| idx >= keep);
+            let too_old = max_age.is_some_and(|age| is_older(*modified, age));
+            if too_many || too_old {
+                fs::remove_file(path)?;
+                removed += 1;
             }
-            None => {}
         }
     }
 
-    Ok(())
+    Ok(removed)
+}
+
+fn is_older(modified: SystemTime, age: Duration) -> bool {
+    SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|elapsed| elapsed > age)
+}
+
+// every output file as (program name, path, last modified)
+// files are named "up_output_<name>_<date>_<time>_<fraction>.txt", the name may contain "_" itself
+fn output_files(tmp_dir_path: &str) -> io::Result<Vec<(String, PathBuf, SystemTime)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(tmp_dir_path)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let Some(stem) = filename
+            .strip_prefix("up_output_")
+            .and_then(|rest| rest.strip_suffix(".txt"))
+        else {
+            continue;
+        };
+        let name = match stem.rsplitn(4, '_').nth(3) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let modified = entry.metadata()?.modified()?;
+        files.push((name, entry.path(), modified));
+    }
+
+    Ok(files)
 }
 
 pub fn check_create_config_dir() -> io::Result<String> {
@@ -58,87 +130,218 @@ pub fn check_create_config_dir() -> io::Result<String> {
     Ok(dir)
 }
 
-// FIXME
-// pub fn error_in_output(program_name: String) -> io::Result<bool> {
-//     let tmp_dir = check_create_tmp_dir()?;
-//     let config_dir = check_create_config_dir()?;
-
-//     for entry in fs::read_dir(tmp_dir)? {
-//         let entry = entry?;
-//         if entry.path().is_file() {
-//             match entry.path().file_name() {
-//                 Some(file) => {
-//                     let filename = file.to_string_lossy();
-//                     if filename.contains(&program_name) {
-//                         let content = fs::read_to_string(entry.path())?;
-//                         if content.contains("error")
-//                             || content.contains("Error")
-//                             || content.contains("ERROR")
-//                         {
-//                             let mut log_file = String::from(&config_dir);
-//                             log_file.push_str("up.log");
-//                             if !log_file.contains(filename.as_ref()) {
-//                                 error!("Error in {}:\n{}", filename, content);
-//                                 return Ok(true);
-//                             }
-//                         }
-//                     }
-//                 }
-//                 None => {}
-//             }
-//         }
-//     }
-
-//     Ok(false)
-// }
+// look up a program the same way the shell would, through the PATH
+pub fn executable_exists(executer: &str) -> bool {
+    find_executable(executer).is_some()
+}
+
+// the file a program name resolves to, on windows including the extensions from PATHEXT
+pub fn find_executable(executer: &str) -> Option<PathBuf> {
+    let path = Path::new(executer);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    let extensions: Vec<String> = match cfg!(target_os = "windows") {
+        true => env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE;.CMD;.BAT;.COM".to_string())
+            .split(';')
+            .map(|ext| ext.to_string())
+            .chain([String::new()])
+            .collect(),
+        false => vec![String::new()],
+    };
+
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|ext| dir.join(format!("{}{}", executer, ext)))
+            .find(|candidate| candidate.is_file())
+    })
+}
 
 pub fn show_log_file(config_dir: &str) -> io::Result<String> {
     let log_path = Path::new(&config_dir).join("up.log");
     match log_path.try_exists()? {
-        true => {
-            return Ok(format!(
-                "{} {}\n{}",
-                "Log location:".italic().dimmed(),
-                &log_path.display(),
-                fs::read_to_string(&log_path)?
-            ));
-        }
-        false => {
-            return Ok(format!(
-                "{} {}",
-                "No log file found:".red().bold().to_string(),
-                log_path.display()
-            ))
+        true => Ok(format!(
+            "{} {}\n{}",
+            "Log location:".italic().dimmed(),
+            &log_path.display(),
+            fs::read_to_string(&log_path)?
+        )),
+        false => Ok(format!(
+            "{} {}",
+            "No log file found:".red().bold(),
+            log_path.display()
+        )),
+    }
+}
+
+// one entry of up.log, as written by flexi_logger's detailed_format
+#[derive(Serialize)]
+pub struct LogEntry {
+    pub time: String,
+    pub level: String,
+    pub module: String,
+    // "file:line" of the log call
+    pub location: String,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct Log {
+    pub path: String,
+    pub entries: Vec<LogEntry>,
+}
+
+// the log file split into its entries, lines without a header belong to the entry before them
+pub fn read_log_file(config_dir: &str) -> io::Result<Log> {
+    let log_path = Path::new(&config_dir).join("up.log");
+    let header = Regex::new(r"^\[([^\]]+)\] (\w+) \[([^\]]*)\] (\S+:\d+): (.*)$").unwrap();
+    let mut entries: Vec<LogEntry> = Vec::new();
+    if log_path.try_exists()? {
+        for line in fs::read_to_string(&log_path)?.lines() {
+            match (header.captures(line), entries.last_mut()) {
+                (Some(caps), _) => entries.push(LogEntry {
+                    time: caps[1].to_string(),
+                    level: caps[2].to_string(),
+                    module: caps[3].to_string(),
+                    location: caps[4].to_string(),
+                    message: caps[5].to_string(),
+                }),
+                (None, Some(entry)) => {
+                    entry.message.push('\n');
+                    entry.message.push_str(line);
+                }
+                (None, None) => {}
+            }
         }
     }
+
+    Ok(Log {
+        path: log_path.display().to_string(),
+        entries,
+    })
 }
 
 pub fn open_tmp(arg: &str) -> io::Result<()> {
     let mut tmp_path = env::temp_dir();
-    tmp_path.push("up_tmp\\");
+    tmp_path.push("up_tmp");
     for entry in fs::read_dir(tmp_path)? {
         let entry = entry?;
-        match entry.path().file_name() {
-            Some(file) => {
-                let filename = file.to_string_lossy();
-                match arg {
-                    "all" => {
+        if let Some(file) = entry.path().file_name() {
+            let filename = file.to_string_lossy();
+            match arg {
+                "all" => {
+                    let content = fs::read_to_string(entry.path())?;
+                    println!("{}:", filename.bold().yellow());
+                    println!("{content}");
+                }
+                _ => {
+                    if filename.contains(&arg.to_string()) {
                         let content = fs::read_to_string(entry.path())?;
                         println!("{}:", filename.bold().yellow());
                         println!("{content}");
                     }
-                    _ => {
-                        if filename.contains(&arg.to_string()) {
-                            let content = fs::read_to_string(entry.path())?;
-                            println!("{}:", filename.bold().yellow());
-                            println!("{content}");
-                        }
-                    }
                 }
             }
-            None => {}
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    // a fresh directory for every test, they run in parallel
+    fn tmp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("up_test_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // an output file of the program that was last written "age" ago
+    fn output_file(dir: &Path, name: &str, stamp: &str, age: Duration) -> PathBuf {
+        let path = dir.join(format!("up_output_{}_{}.txt", name, stamp));
+        File::create(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        path
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = output_files(dir.to_str().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn output_files_with_underscores_in_the_name() {
+        let dir = tmp_dir("names");
+        output_file(&dir, "cargo", "20240101_120000_123", Duration::ZERO);
+        output_file(&dir, "pip_user", "20240101_120000_123", Duration::ZERO);
+        output_file(&dir, "a_b_c", "20240101_120000_123", Duration::ZERO);
+        // not written by up
+        File::create(dir.join("up_output_broken.txt")).unwrap();
+        File::create(dir.join("notes.txt")).unwrap();
+
+        assert_eq!(names(&dir), ["a_b_c", "cargo", "pip_user"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn retention_keeps_the_newest_runs_per_program() {
+        let dir = tmp_dir("keep_runs");
+        let newest = output_file(&dir, "cargo", "20240103_120000_123", DAY);
+        output_file(&dir, "cargo", "20240102_120000_123", 2 * DAY);
+        output_file(&dir, "cargo", "20240101_120000_123", 3 * DAY);
+        let other = output_file(&dir, "pip_user", "20240101_120000_123", 3 * DAY);
+
+        assert_eq!(
+            apply_retention(dir.to_str().unwrap(), Some(1), None).unwrap(),
+            2
+        );
+        assert!(newest.exists());
+        assert!(other.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn retention_removes_old_files() {
+        let dir = tmp_dir("keep_days");
+        let recent = output_file(&dir, "cargo", "20240103_120000_123", DAY);
+        output_file(&dir, "cargo", "20240101_120000_123", 10 * DAY);
+
+        assert_eq!(
+            apply_retention(dir.to_str().unwrap(), None, Some(7)).unwrap(),
+            1
+        );
+        assert!(recent.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn retention_with_too_many_days_keeps_everything() {
+        let dir = tmp_dir("overflow");
+        output_file(&dir, "cargo", "20240101_120000_123", 10 * DAY);
+
+        assert_eq!(
+            apply_retention(dir.to_str().unwrap(), None, Some(u64::MAX)).unwrap(),
+            0
+        );
+        assert_eq!(names(&dir), ["cargo"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
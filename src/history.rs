@@ -0,0 +1,374 @@
+use chrono::{DateTime, Local};
+use log::error;
+use owo_colors::colored::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::dir_work::check_create_config_dir;
+use crate::programs::{Program, State};
+use crate::report::{print_entries, print_text, print_value, Format};
+
+// one run of "up update", "up info" or "up outdated", stored as history/<id>.ron in the config dir
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Run {
+    // the start time, sortable, e.g. "20261018_115520_627"
+    pub id: String,
+    pub mode: String,
+    // rfc 3339 timestamps
+    pub started: String,
+    pub finished: String,
+    // in seconds
+    pub duration: f64,
+    pub programs: Vec<ProgramRun>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ProgramRun {
+    pub name: String,
+    pub state: State,
+    pub exit_code: Option<i32>,
+    // in seconds, 0 if the program never started
+    pub duration: f64,
+    pub attempts: u32,
+    // the output file, if it was written
+    pub output: Option<String>,
+    pub msg: Vec<String>,
+    // lines of the output that matched the error or warning patterns
+    #[serde(default)]
+    pub matches: Vec<String>,
+    // pending updates, only set by "up outdated"
+    #[serde(default)]
+    pub pending: Option<usize>,
+    // the versions from cmd_for_version, only set by "up update"
+    #[serde(default)]
+    pub version_before: Option<String>,
+    #[serde(default)]
+    pub version_after: Option<String>,
+}
+
+fn history_dir() -> io::Result<PathBuf> {
+    let dir = Path::new(&check_create_config_dir()?).join("history");
+    if !dir.exists() {
+        fs::create_dir(&dir)?;
+    }
+
+    Ok(dir)
+}
+
+impl Run {
+    pub fn new(mode: &str, started: DateTime<Local>, programs: &[Program]) -> Run {
+        let finished = Local::now();
+        Run {
+            id: started.format("%Y%m%d_%H%M%S_%3f").to_string(),
+            mode: mode.to_string(),
+            started: started.to_rfc3339(),
+            finished: finished.to_rfc3339(),
+            duration: (finished - started).num_milliseconds() as f64 / 1000.0,
+            programs: programs.iter().map(ProgramRun::from).collect(),
+        }
+    }
+}
+
+impl From<&Program> for ProgramRun {
+    fn from(program: &Program) -> ProgramRun {
+        ProgramRun {
+            name: program.name.clone(),
+            state: program.state,
+            exit_code: program.status.and_then(|status| status.code()),
+            duration: program
+                .duration
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default(),
+            attempts: program.attempts,
+            output: match program.has_output && Path::new(&program.outputfile).exists() {
+                true => Some(program.outputfile.clone()),
+                false => None,
+            },
+            msg: program.msg.clone(),
+            matches: program.matches.clone(),
+            pending: program.pending,
+            version_before: program.version_before.clone(),
+            version_after: program.version_after.clone(),
+        }
+    }
+}
+
+// store a finished run
+pub fn record_run(run: &Run) -> io::Result<()> {
+    let serialized = ron::ser::to_string_pretty(run, PrettyConfig::new())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    fs::write(history_dir()?.join(format!("{}.ron", run.id)), serialized)?;
+
+    Ok(())
+}
+
+// every stored run, the oldest first
+pub fn load_runs() -> io::Result<Vec<Run>> {
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(history_dir()?)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "ron") {
+            // a broken entry must not hide the others
+            match fs::read_to_string(&path).map(|content| ron::de::from_str::<Run>(&content)) {
+                Ok(Ok(run)) => runs.push(run),
+                Ok(Err(err)) => error!("Unable to read {}: {}", path.display(), err),
+                Err(err) => error!("Unable to read {}: {}", path.display(), err),
+            }
+        }
+    }
+    runs.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(runs)
+}
+
+// find a run by its id, the start of its id or "last"
+pub fn find_run(runs: &[Run], id: &str) -> io::Result<Run> {
+    let found: Vec<_> = match id {
+        "last" => runs.last().into_iter().collect(),
+        _ => runs.iter().filter(|run| run.id.starts_with(id)).collect(),
+    };
+
+    match found.as_slice() {
+        [run] => Ok((*run).clone()),
+        [] => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No run found for \"{}\" (see \"up history\")", id),
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "\"{}\" matches {} runs, use more of the id",
+                id,
+                found.len()
+            ),
+        )),
+    }
+}
+
+fn count(run: &Run, states: &[State]) -> usize {
+    run.programs
+        .iter()
+        .filter(|program| states.contains(&program.state))
+        .count()
+}
+
+fn local_time(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+fn started(run: &Run) -> String {
+    local_time(&run.started)
+}
+
+pub fn list_runs(runs: &[Run], format: Format, plain: bool) {
+    if !format.is_text() {
+        print_entries(runs, format);
+        return;
+    }
+    if runs.is_empty() {
+        print_text(&"No runs recorded yet".dimmed().to_string(), plain);
+        return;
+    }
+
+    print_text(&"Runs:".bold().yellow().to_string(), plain);
+    for run in runs {
+        let failed = count(run, &[State::Failed, State::TimedOut]);
+        let warnings = count(run, &[State::Warning]);
+        print_text(
+            &format!(
+                "{}  {}  {:<8} {:>8}  {} {}{}",
+                run.id.dimmed(),
+                started(run),
+                run.mode,
+                format!("{:.1}s", run.duration),
+                format!("✔ {}", count(run, &[State::Success])).truecolor(59, 179, 140),
+                match warnings {
+                    0 => String::new(),
+                    _ => format!("⚠ {} ", warnings)
+                        .truecolor(250, 140, 0)
+                        .to_string(),
+                },
+                match failed {
+                    0 => String::new(),
+                    _ => format!("✘ {}", failed).truecolor(250, 0, 104).to_string(),
+                }
+            ),
+            plain,
+        );
+    }
+}
+
+pub fn show_run(run: &Run, format: Format, plain: bool) {
+    if !format.is_text() {
+        print_value(run, format);
+        return;
+    }
+    print_text(
+        &format!(
+            "{} {} {}",
+            format!("Run {}", run.id).bold().yellow(),
+            format!("({})", run.mode).dimmed(),
+            format!("started {}, took {:.1}s", started(run), run.duration).dimmed()
+        ),
+        plain,
+    );
+    for program in &run.programs {
+        let state = match program.state {
+            State::Success => match program.pending {
+                Some(0) => "up to date".truecolor(59, 179, 140).to_string(),
+                Some(pending) => format!("{} pending", pending)
+                    .truecolor(127, 111, 219)
+                    .to_string(),
+                None => "done".truecolor(59, 179, 140).to_string(),
+            },
+            State::Failed => match program.exit_code {
+                Some(0) if !program.matches.is_empty() => "failed (error in output)".to_string(),
+                Some(code) => format!("failed (exit {})", code),
+                None => "failed".to_string(),
+            }
+            .truecolor(250, 0, 104)
+            .to_string(),
+            State::TimedOut => "timed out".truecolor(250, 140, 0).to_string(),
+            State::Skipped => "skipped".truecolor(127, 111, 219).to_string(),
+            State::Pending => "pending".dimmed().to_string(),
+            State::Warning => "completed with warnings".truecolor(250, 140, 0).to_string(),
+        };
+        print_text(
+            &format!(
+                "  {:<10} {} {}",
+                program.name.bold(),
+                state,
+                format!("{:.1}s", program.duration).dimmed()
+            ),
+            plain,
+        );
+        match (&program.version_before, &program.version_after) {
+            (Some(before), Some(after)) if before != after => print_text(
+                &format!(
+                    "      {} {} → {}",
+                    "version:".truecolor(127, 111, 219),
+                    before,
+                    after
+                ),
+                plain,
+            ),
+            (_, Some(version)) | (Some(version), None) => print_text(
+                &format!("      {} {}", "version:".truecolor(127, 111, 219), version),
+                plain,
+            ),
+            _ => {}
+        }
+        if program.attempts > 1 {
+            print_text(
+                &format!(
+                    "      {} {}",
+                    "attempts:".truecolor(127, 111, 219),
+                    program.attempts
+                ),
+                plain,
+            );
+        }
+        for line in &program.matches {
+            print_text(
+                &format!("      {} {}", "matched:".truecolor(127, 111, 219), line),
+                plain,
+            );
+        }
+        if let Some(output) = &program.output {
+            // "up clean" or the retention may have removed it in the meantime
+            let removed = match Path::new(output).exists() {
+                true => String::new(),
+                false => format!(" {}", "(removed)".dimmed()),
+            };
+            print_text(
+                &format!(
+                    "      {} {}{}",
+                    "output:".truecolor(127, 111, 219),
+                    output,
+                    removed
+                ),
+                plain,
+            );
+        }
+        if !program.msg.is_empty() {
+            print_text(
+                &format!(
+                    "      {} {}",
+                    "message:".truecolor(127, 111, 219),
+                    program.msg.join(" ")
+                ),
+                plain,
+            );
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VersionChange<'a> {
+    run: &'a str,
+    started: &'a str,
+    name: &'a str,
+    before: &'a str,
+    after: &'a str,
+}
+
+// every version change in the history, the oldest first
+pub fn list_versions(runs: &[Run], programs: &[String], format: Format, plain: bool) {
+    let mut changes = Vec::new();
+    for run in runs {
+        for program in &run.programs {
+            if !programs.is_empty() && !programs.contains(&program.name) {
+                continue;
+            }
+            let (Some(before), Some(after)) = (&program.version_before, &program.version_after)
+            else {
+                continue;
+            };
+            if before != after {
+                changes.push(VersionChange {
+                    run: &run.id,
+                    started: &run.started,
+                    name: &program.name,
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+
+    if !format.is_text() {
+        print_entries(&changes, format);
+        return;
+    }
+    if changes.is_empty() {
+        print_text(
+            &"No version changes recorded yet".dimmed().to_string(),
+            plain,
+        );
+        return;
+    }
+    print_text(&"Version changes:".bold().yellow().to_string(), plain);
+    for change in changes {
+        print_text(
+            &format!(
+                "{}  {:<10} {} → {}",
+                local_time(change.started),
+                change.name.bold(),
+                change.before.dimmed(),
+                change.after.truecolor(59, 179, 140)
+            ),
+            plain,
+        );
+    }
+}
@@ -3,31 +3,53 @@
 
 pub mod app;
 pub mod commands;
+pub mod config;
 pub mod dir_work;
+pub mod history;
+pub mod presets;
 pub mod programs;
+pub mod report;
 use crate::app::up;
-use crate::commands::{confirm, get_sys, init, list_programs};
+use crate::commands::{
+    any_failed, confirm, dry_run, filter_programs, get_sys, init, kill_running, list_presets,
+    list_programs,
+};
+use crate::config::{
+    add_app, app_from_args, app_from_prompts, detected_config, diff, get_app, print_app,
+    print_diff, remove_app, replace_app, write_config,
+};
 use crate::dir_work::*;
-use crate::programs::load_programs;
+use crate::history::{find_run, list_runs, list_versions, load_runs, show_run};
+use crate::programs::{
+    apply_retries, apply_timeout, default_config, load_programs, validate_config, App, Settings,
+};
+use crate::report::{print_entries, print_value, Format, Stream};
 
+use clap::error::ErrorKind;
+use clap_complete::{generate, Shell};
 use flexi_logger::{detailed_format, Duplicate, FileSpec, Logger};
+use indicatif::HumanDuration;
 use log::error;
 use owo_colors::colored::*;
 
+use std::fs;
+use std::io::{self, IsTerminal};
 use std::path::Path;
 use std::process;
+use std::time::Duration;
 
 fn main() {
     // handle Ctrl+C
     ctrlc::set_handler(move || {
         println!(
-            "{} {} {} {}",
+            "{} 🤬 {} ☠",
             "Received Ctrl-C!".bold().red(),
-            "🤬",
             "Exit program!".bold().red(),
-            "☠",
         );
-        process::exit(0)
+        // programs in their own process group don't get the Ctrl-C from the terminal
+        kill_running();
+        // 128 + SIGINT, like a shell reports an interrupted command
+        process::exit(130)
     })
     .expect("Error setting Ctrl-C handler");
 
@@ -59,47 +81,292 @@ fn main() {
 
     // set up the programs from config file
     let ron = Path::new(&config_dir).join("up_config.ron");
-    let programs = load_programs(&ron).unwrap_or_else(|err| {
-        error!("Unable to load programs from {}: {}", ron.display(), err);
-        process::exit(1);
-    });
+    // a broken config only stops "up check" from running after the arguments are known
+    let (mut programs, mut settings, load_error) = match load_programs(&ron) {
+        Ok((programs, settings)) => (programs, settings, None),
+        Err(err) => (Vec::new(), Settings::default(), Some(err)),
+    };
+    let exit_on_load_error = || {
+        if let Some(err) = &load_error {
+            error!("Unable to load programs from {}: {}", ron.display(), err);
+            process::exit(1);
+        }
+    };
 
     // handle arguments
-    let matches = up().get_matches();
+    let names: Vec<_> = programs
+        .iter()
+        .map(|program| program.name.clone())
+        .collect();
+    let matches = up(&names).try_get_matches().unwrap_or_else(|err| {
+        // program names can't be validated without a config
+        if !matches!(
+            err.kind(),
+            ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+        ) {
+            exit_on_load_error();
+        }
+        err.exit()
+    });
+    // "check" reports a broken config, "init" replaces it, the history doesn't need it
+    if !matches!(
+        matches.subcommand_name(),
+        Some("check") | Some("init") | Some("history")
+    ) {
+        exit_on_load_error();
+    }
     let verbose_flag = matches.get_flag("verbose");
+    let dry_run_flag = matches.get_flag("dry-run");
+    if let Some(timeout) = matches.get_one::<u64>("timeout") {
+        apply_timeout(&mut programs, Duration::from_secs(*timeout));
+    }
+    if let Some(retries) = matches.get_one::<u32>("retries") {
+        apply_retries(&mut programs, *retries);
+    }
+    if let Some(jobs) = matches.get_one::<u64>("jobs") {
+        settings.max_parallel = Some(*jobs as usize);
+    }
+    let format = *matches.get_one::<Format>("format").expect("default");
+    settings.format = format;
+    // spinners are only readable in a terminal, cron and ci logs get plain lines
+    settings.plain =
+        matches.get_flag("plain") || !io::stdout().is_terminal() || !io::stderr().is_terminal();
+    settings.stream = matches.get_one::<Stream>("stream").copied();
+    let yes_flag = matches.get_flag("yes");
+
+    // leave out programs for this run
+    let mut only: Vec<String> = matches
+        .get_many::<String>("only")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    for subcommand in ["info", "outdated", "update"] {
+        if let Some(sub_match) = matches.subcommand_matches(subcommand) {
+            only.extend(
+                sub_match
+                    .get_many::<String>("PROGRAM")
+                    .unwrap_or_default()
+                    .cloned(),
+            );
+        }
+    }
+    let mut skip: Vec<String> = matches
+        .get_many::<String>("skip")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    if let Some(sub_match) = matches.subcommand_matches("exclude") {
+        skip.extend(
+            sub_match
+                .get_many::<String>("PROGRAM")
+                .expect("required")
+                .cloned(),
+        );
+    }
+    let programs = filter_programs(programs, &only, &skip).unwrap_or_else(|err| {
+        error!("{}", err);
+        process::exit(1);
+    });
+
     match matches.subcommand() {
-        Some(("clean", _)) => {
+        Some(("add", sub_match)) => {
+            let default = App {
+                start_extern: true,
+                has_output: true,
+                ..Default::default()
+            };
+            let app = match sub_match.contains_id("name") || sub_match.contains_id("preset") {
+                true => app_from_args(sub_match, default),
+                false => app_from_prompts(app_from_args(sub_match, default)),
+            };
+            let expanded = app.clone().expanded().unwrap_or_else(|err| {
+                error!("Unable to add program: {}", err);
+                process::exit(1);
+            });
+            if expanded.name.is_empty() || expanded.executer.is_empty() {
+                error!("A program needs at least a name and an executer, or a preset");
+                process::exit(1);
+            }
+            if let Err(err) = add_app(&ron, &app) {
+                error!("Unable to add {}: {}", expanded.name, err);
+                process::exit(1);
+            }
+            println!("{} {}", "Added:".green(), expanded.name);
+        }
+        Some(("check", _)) => {
+            let (errors, warnings) = validate_config(&ron).unwrap_or_else(|err| {
+                error!("Unable to read {}: {}", ron.display(), err);
+                process::exit(1);
+            });
+            for err in &errors {
+                println!("{} {}", "error:".red().bold(), err);
+            }
+            for warning in &warnings {
+                println!("{} {}", "warning:".yellow().bold(), warning);
+            }
+            match errors.is_empty() {
+                true => println!(
+                    "✔ {} {}",
+                    "config is valid".truecolor(59, 179, 140),
+                    format!("({} warnings)", warnings.len()).dimmed()
+                ),
+                false => {
+                    println!(
+                        "✘ {}",
+                        format!("{} errors, {} warnings", errors.len(), warnings.len())
+                            .truecolor(250, 0, 104)
+                    );
+                    process::exit(1);
+                }
+            }
+        }
+        Some(("clean", sub_match)) => {
+            let programs: Vec<String> = sub_match
+                .get_many::<String>("program")
+                .unwrap_or_default()
+                .cloned()
+                .collect();
+            let older_than = sub_match.get_one::<Duration>("older-than").copied();
+            let which = match (programs.is_empty(), older_than) {
+                (true, None) => "all temporary files".to_string(),
+                (true, Some(age)) => {
+                    format!("all temporary files older than {}", HumanDuration(age))
+                }
+                (false, None) => format!("all temporary files of {}", programs.join(", ")),
+                (false, Some(age)) => format!(
+                    "all temporary files of {} older than {}",
+                    programs.join(", "),
+                    HumanDuration(age)
+                ),
+            };
             let msg = format!(
                 "{}",
-                "Do you really want to delete all temporary files? (y/n)"
+                format!("Do you really want to delete {}? (y/n)", which)
                     .red()
                     .bold()
             );
-            if confirm(&msg) {
-                if let Err(err) = remove_tmps(&tmp_dir) {
-                    error!("Error while cleaning temporary directory: {}", err);
-                    process::exit(1);
-                } else {
-                    println!("{} {}", "🗑️", "All temporary files removed".bold().red());
+            if confirm(&msg, yes_flag) {
+                match remove_tmps(&tmp_dir, &programs, older_than) {
+                    Ok(removed) => println!(
+                        "🗑️ {}",
+                        format!("{} temporary files removed", removed).bold().red()
+                    ),
+                    Err(err) => {
+                        error!("Error while cleaning temporary directory: {}", err);
+                        process::exit(1);
+                    }
                 }
             } else {
                 println!("Nevermind then");
             }
         }
         Some(("info", sub_match)) => {
-            if let Err(err) = init(programs, "info") {
+            if dry_run_flag {
+                dry_run(&programs, "info", &settings);
+                return;
+            }
+            let finished = init(programs, "info", &settings).unwrap_or_else(|err| {
                 error!("Error executing cmds: {}", err);
                 process::exit(1);
+            });
+            if sub_match.get_flag("verbose") && format.is_text() {
+                if let Err(err) = open_tmp("all") {
+                    error!("Unable to open output files: {}", err);
+                    process::exit(1);
+                }
+            }
+            if any_failed(&finished) {
+                process::exit(1);
             }
-            if sub_match.get_flag("verbose") {
+        }
+        Some(("outdated", sub_match)) => {
+            if dry_run_flag {
+                dry_run(&programs, "outdated", &settings);
+                return;
+            }
+            let finished = init(programs, "outdated", &settings).unwrap_or_else(|err| {
+                error!("Error executing cmds: {}", err);
+                process::exit(1);
+            });
+            if sub_match.get_flag("verbose") && format.is_text() {
                 if let Err(err) = open_tmp("all") {
                     error!("Unable to open output files: {}", err);
                     process::exit(1);
                 }
             }
-            // TODO add info about one program if program is given after "up info [PROGRAM]"
-            // info(sub_matches.get_one::<String>("PROGRAM").expect("required"));
+            if any_failed(&finished) {
+                process::exit(1);
+            }
+        }
+        Some(("completions", sub_match)) => {
+            let shell = *sub_match.get_one::<Shell>("SHELL").expect("required");
+            generate(shell, &mut up(&names), "up", &mut io::stdout());
+        }
+        Some(("edit", sub_match)) => {
+            let name = sub_match.get_one::<String>("PROGRAM").expect("required");
+            let current = get_app(&ron, name).unwrap_or_else(|err| {
+                error!("Unable to edit {}: {}", name, err);
+                process::exit(1);
+            });
+            let app_flags = [
+                "preset",
+                "name",
+                "symbol",
+                "executer",
+                "start-extern",
+                "has-output",
+                "update",
+                "info",
+                "timeout",
+                "retries",
+                "after",
+            ];
+            let app = match app_flags.iter().any(|id| sub_match.contains_id(id)) {
+                true => app_from_args(sub_match, current),
+                false => app_from_prompts(current),
+            };
+            if let Err(err) = replace_app(&ron, name, &app) {
+                error!("Unable to edit {}: {}", name, err);
+                process::exit(1);
+            }
+            println!("{}", "Changed:".green());
+            if let Err(err) = print_app(&app) {
+                error!("Unable to display {}: {}", app.name, err);
+            }
+        }
+        Some(("history", sub_match)) => {
+            let runs = load_runs().unwrap_or_else(|err| {
+                error!("Unable to read the history: {}", err);
+                process::exit(1);
+            });
+            if sub_match.contains_id("versions") {
+                let programs: Vec<String> = sub_match
+                    .get_many::<String>("versions")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect();
+                list_versions(&runs, &programs, format, settings.plain);
+                return;
+            }
+            match sub_match.get_one::<String>("RUN") {
+                Some(id) => match find_run(&runs, id) {
+                    Ok(run) => show_run(&run, format, settings.plain),
+                    Err(err) => {
+                        error!("{}", err);
+                        process::exit(1);
+                    }
+                },
+                None => list_runs(&runs, format, settings.plain),
+            }
         }
+        Some(("log", _)) if !format.is_text() => match read_log_file(&config_dir) {
+            Ok(log) if format == Format::Jsonl => print_entries(&log.entries, format),
+            Ok(log) => print_value(&log, format),
+            Err(err) => {
+                error!("Unable to read logs: {}", err);
+                process::exit(1);
+            }
+        },
         Some(("log", _)) => {
             if let Ok(logs) = show_log_file(&config_dir) {
                 println!("{}", "Available logs:".bold().yellow());
@@ -109,8 +376,32 @@ fn main() {
                 process::exit(1);
             }
         }
+        Some(("presets", _)) => {
+            list_presets();
+        }
+        Some(("remove", sub_match)) => {
+            let name = sub_match.get_one::<String>("PROGRAM").expect("required");
+            let msg = format!(
+                "{}",
+                format!(
+                    "Do you really want to remove {} from the config file? (y/n)",
+                    name
+                )
+                .red()
+                .bold()
+            );
+            if confirm(&msg, yes_flag) {
+                if let Err(err) = remove_app(&ron, name) {
+                    error!("Unable to remove {}: {}", name, err);
+                    process::exit(1);
+                }
+                println!("{} {}", "Removed:".red(), name);
+            } else {
+                println!("Nevermind then");
+            }
+        }
         Some(("sys", _)) => {
-            get_sys();
+            get_sys(format);
         }
         Some(("open", sub_match)) => {
             let arg = sub_match
@@ -122,39 +413,64 @@ fn main() {
                 process::exit(1);
             }
         }
-        Some(("list", _)) => {
-            list_programs(&programs);
-        }
-        // FIXME
-        // Some(("exclude", _)) => {
-        // let apps: Vec<_> = sub_matches
-        //     .get_many::<String>("PROGRAM")
-        //     .expect("required")
-        //     .map(|s| s.as_str())
-        //     .collect();
-        // let programs = apps.join(", ");
-
-        // let filtered = exclude(&programs).expect("Error trying to exclude programs");
-        // for program in filtered {
-        //     println!("{}", program);
-        // }
+        Some(("init", sub_match)) => {
+            let new_content = match sub_match.get_flag("detect") {
+                true => detected_config().unwrap_or_else(|err| {
+                    error!("Unable to create a config file: {}", err);
+                    process::exit(1);
+                }),
+                false => default_config(),
+            };
+            let old_content = fs::read_to_string(&ron).unwrap_or_default();
+            if old_content == new_content {
+                println!("{}", "Nothing to change".dimmed());
+                return;
+            }
+            print_diff(&diff(&old_content, &new_content));
 
-        // if let Err(err) = init(filtered, "update") {
-        //     error!("Error executing cmds: {}", err);
-        //     process::exit(1);
-        // }
-        // }
+            let msg = format!(
+                "{}",
+                format!("Do you really want to overwrite {}? (y/n)", ron.display())
+                    .red()
+                    .bold()
+            );
+            if confirm(&msg, yes_flag) {
+                if let Err(err) = write_config(&ron, &new_content) {
+                    error!("Unable to write {}: {}", ron.display(), err);
+                    process::exit(1);
+                }
+                println!("{} {}", "Written:".green(), ron.display());
+            } else {
+                println!("Nevermind then");
+            }
+        }
+        Some(("list", _)) => {
+            list_programs(&programs, format);
+        }
+        // "update", "exclude" and no subcommand at all
         _ => {
-            if let Err(err) = init(programs, "update") {
+            if dry_run_flag {
+                dry_run(&programs, "update", &settings);
+                return;
+            }
+            let finished = init(programs, "update", &settings).unwrap_or_else(|err| {
                 error!("Error executing cmds: {}", err);
                 process::exit(1);
-            }
-            if verbose_flag {
+            });
+            let verbose_flag = verbose_flag
+                || matches
+                    .subcommand_matches("update")
+                    .is_some_and(|sub_match| sub_match.get_flag("verbose"));
+            // the json output lists the output files instead
+            if verbose_flag && format.is_text() {
                 if let Err(err) = open_tmp("all") {
                     error!("Unable to open output files: {}", err);
                     process::exit(1);
                 }
             }
+            if any_failed(&finished) {
+                process::exit(1);
+            }
         }
     }
 }
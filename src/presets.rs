@@ -0,0 +1,204 @@
+use crate::dir_work::executable_exists;
+
+// known package managers, used with preset: Some("<name>") in the config file
+#[derive(Clone)]
+pub struct Preset {
+    pub name: &'static str,
+    // the program that has to be installed for the preset to work
+    pub binary: &'static str,
+    pub executer: &'static str,
+    pub cmd_for_update: &'static str,
+    pub cmd_for_info: &'static str,
+    // lists the pending updates, "outdated_pattern" picks the lines to count
+    pub cmd_for_outdated: Option<&'static str>,
+    pub outdated_pattern: Option<&'static str>,
+    // exit codes besides 0 that cmd_for_outdated uses when it worked, e.g. dnf exits with 100 if anything is outdated
+    pub outdated_exit_codes: &'static [i32],
+    // the commands need the shell for "&&" or pipes
+    pub shell: bool,
+    // presets that should be updated first, if they are detected as well
+    pub after: &'static [&'static str],
+}
+
+pub fn presets() -> Vec<Preset> {
+    // updating every pip package needs a small pipeline in each shell
+    let pip_update = match cfg!(target_os = "windows") {
+        true => {
+            "list --outdated --format=freeze | ForEach-Object { pip install -U $_.split('==')[0] }"
+        }
+        false => "list --outdated --format=freeze | cut -d = -f 1 | xargs -n1 pip install -U",
+    };
+
+    vec![
+        Preset {
+            name: "apt",
+            binary: "apt-get",
+            executer: "sudo",
+            cmd_for_update: "-n apt-get update && sudo -n apt-get upgrade -y",
+            cmd_for_info: "-n apt list --upgradable",
+            cmd_for_outdated: Some("-n apt list --upgradable"),
+            outdated_pattern: Some(r"\[upgradable from"),
+            outdated_exit_codes: &[],
+            shell: true,
+            after: &[],
+        },
+        Preset {
+            name: "brew",
+            binary: "brew",
+            executer: "brew",
+            cmd_for_update: "update && brew upgrade",
+            cmd_for_info: "outdated",
+            cmd_for_outdated: Some("outdated"),
+            outdated_pattern: None,
+            outdated_exit_codes: &[],
+            shell: true,
+            after: &[],
+        },
+        Preset {
+            name: "cargo",
+            binary: "cargo",
+            executer: "cargo",
+            cmd_for_update: "install-update -a",
+            cmd_for_info: "install-update -l",
+            cmd_for_outdated: Some("install-update -l"),
+            outdated_pattern: Some(r"\sYes$"),
+            outdated_exit_codes: &[],
+            shell: false,
+            after: &["rustup"],
+        },
+        Preset {
+            name: "dnf",
+            binary: "dnf",
+            executer: "sudo",
+            cmd_for_update: "-n dnf upgrade -y",
+            cmd_for_info: "-n dnf list --upgrades",
+            cmd_for_outdated: Some("-n dnf check-update -q"),
+            outdated_pattern: None,
+            outdated_exit_codes: &[100],
+            shell: false,
+            after: &[],
+        },
+        Preset {
+            name: "flatpak",
+            binary: "flatpak",
+            executer: "flatpak",
+            cmd_for_update: "update -y --noninteractive",
+            cmd_for_info: "remote-ls --updates",
+            cmd_for_outdated: Some("remote-ls --updates"),
+            outdated_pattern: None,
+            outdated_exit_codes: &[],
+            shell: false,
+            after: &[],
+        },
+        Preset {
+            name: "gem",
+            binary: "gem",
+            executer: "gem",
+            cmd_for_update: "update",
+            cmd_for_info: "outdated",
+            cmd_for_outdated: Some("outdated"),
+            outdated_pattern: None,
+            outdated_exit_codes: &[],
+            shell: false,
+            after: &[],
+        },
+        Preset {
+            name: "npm",
+            binary: "npm",
+            executer: "npm",
+            cmd_for_update: "update -g",
+            cmd_for_info: "ls -g --depth=0",
+            cmd_for_outdated: Some("outdated -g --parseable"),
+            outdated_pattern: None,
+            outdated_exit_codes: &[1],
+            // powershell runs the npm.cmd shim like any other command
+            shell: cfg!(target_os = "windows"),
+            after: &[],
+        },
+        Preset {
+            name: "pacman",
+            binary: "pacman",
+            executer: "sudo",
+            cmd_for_update: "-n pacman -Syu --noconfirm",
+            cmd_for_info: "-n pacman -Qu",
+            cmd_for_outdated: Some("-n pacman -Qu"),
+            outdated_pattern: None,
+            outdated_exit_codes: &[1],
+            shell: false,
+            after: &[],
+        },
+        Preset {
+            name: "pip",
+            binary: "pip",
+            executer: "pip",
+            cmd_for_update: pip_update,
+            cmd_for_info: "list --outdated",
+            cmd_for_outdated: Some("list --outdated --format=freeze"),
+            outdated_pattern: None,
+            outdated_exit_codes: &[],
+            shell: true,
+            after: &[],
+        },
+        Preset {
+            name: "pipx",
+            binary: "pipx",
+            executer: "pipx",
+            cmd_for_update: "upgrade-all",
+            cmd_for_info: "list --short",
+            cmd_for_outdated: None,
+            outdated_pattern: None,
+            outdated_exit_codes: &[],
+            shell: false,
+            after: &[],
+        },
+        Preset {
+            name: "rustup",
+            binary: "rustup",
+            executer: "rustup",
+            cmd_for_update: "update",
+            cmd_for_info: "check",
+            cmd_for_outdated: Some("check"),
+            outdated_pattern: Some(r"Update available"),
+            outdated_exit_codes: &[100],
+            shell: false,
+            after: &[],
+        },
+        Preset {
+            name: "snap",
+            binary: "snap",
+            executer: "sudo",
+            cmd_for_update: "-n snap refresh",
+            cmd_for_info: "-n snap refresh --list",
+            cmd_for_outdated: Some("-n snap refresh --list"),
+            outdated_pattern: Some(r"^\S+\s+\S+\s+\d+"),
+            outdated_exit_codes: &[],
+            shell: false,
+            after: &[],
+        },
+        Preset {
+            name: "winget",
+            binary: "winget",
+            executer: "winget",
+            cmd_for_update:
+                "upgrade --all --silent --accept-source-agreements --accept-package-agreements",
+            cmd_for_info: "upgrade --accept-source-agreements",
+            cmd_for_outdated: Some("upgrade --accept-source-agreements"),
+            outdated_pattern: Some(r"^(\d+) upgrades? available"),
+            outdated_exit_codes: &[],
+            shell: false,
+            after: &[],
+        },
+    ]
+}
+
+pub fn find_preset(name: &str) -> Option<Preset> {
+    presets().into_iter().find(|preset| preset.name == name)
+}
+
+// every preset whose program is installed on this machine
+pub fn detect_presets() -> Vec<Preset> {
+    presets()
+        .into_iter()
+        .filter(|preset| executable_exists(preset.binary))
+        .collect()
+}
@@ -1,87 +1,472 @@
 use chrono::Local;
-use log::error;
-use serde::Deserialize;
+use log::{error, warn};
+use regex::Regex;
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
 use std::{
-    fmt,
-    fs::{self, File},
-    io,
-    path::PathBuf,
-    process,
+    collections::{BTreeMap, HashSet},
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, ExitStatus, Output},
+    time::Duration,
 };
 
-use crate::dir_work::check_create_tmp_dir;
+use crate::dir_work::{check_create_tmp_dir, executable_exists, find_executable};
+use crate::presets::find_preset;
+use crate::report::{Format, Stream};
 
-const PLACEHOLDER_THRESHOLD: usize = 8;
+pub const PLACEHOLDER_THRESHOLD: usize = 8;
+// seconds to wait before the first retry if retry_delay is not set
+pub const DEFAULT_RETRY_DELAY: u64 = 5;
+// the doubled delays stop growing here, a longer retry_delay is still used as it is
+pub const MAX_RETRY_DELAY: u64 = 10 * 60;
+// finds versions like "1.75.0" or "2.4.1-beta.2" if version_pattern is not set
+pub const DEFAULT_VERSION_PATTERN: &str = r"\d+(?:\.\d+)+(?:[-+][0-9A-Za-z.]+)?";
 
 #[derive(Clone, Deserialize)]
 struct Config {
+    // the shell used for commands that need one
+    // defaults to "powershell" on windows and "sh" everywhere else
+    #[serde(default)]
+    shell: Option<String>,
+    // how many programs run at the same time, all at once if not set
+    #[serde(default)]
+    max_parallel: Option<usize>,
+    // shell commands that run once before the first and after the last update
+    #[serde(default)]
+    before_all: Vec<String>,
+    #[serde(default)]
+    after_all: Vec<String>,
+    // regexes matched against every line of the output of a successful command, used for every program
+    #[serde(default)]
+    error_patterns: Vec<String>,
+    #[serde(default)]
+    warning_patterns: Vec<String>,
+    // finds the version in the output of cmd_for_version, used for every program without its own
+    #[serde(default)]
+    version_pattern: Option<String>,
+    // retention of the output files, applied after every run
+    #[serde(default)]
+    keep_runs: Option<usize>,
+    #[serde(default)]
+    keep_days: Option<u64>,
     apps: Vec<App>,
 }
 
-#[derive(Clone, Deserialize)]
-struct App {
-    name: String,
-    symbol: Option<String>,
-    executer: String,
-    start_extern: bool,
-    has_output: bool,
-    cmd_for_update: Option<String>,
-    cmd_for_info: Option<String>,
+// global options from the config root, some can be overwritten by command line flags
+#[derive(Clone, Default)]
+pub struct Settings {
+    pub max_parallel: Option<usize>,
+    pub before_all: Vec<Cmd>,
+    pub after_all: Vec<Cmd>,
+    pub keep_runs: Option<usize>,
+    pub keep_days: Option<u64>,
+    // only set from the command line
+    pub format: Format,
+    pub plain: bool,
+    pub stream: Option<Stream>,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct App {
+    // a known package manager, fills in every field that is not set explicitly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub symbol: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub executer: String,
+    #[serde(default = "default_true")]
+    pub start_extern: bool,
+    #[serde(default = "default_true")]
+    pub has_output: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmd_for_update: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmd_for_info: Option<String>,
+    // lists the pending updates for "up outdated", one line per update unless outdated_pattern says otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd_for_outdated: Option<String>,
+    // only lines matching this regex are counted, or the number in its first group if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outdated_pattern: Option<String>,
+    // exit codes besides 0 that cmd_for_outdated uses when it worked, any other one fails the check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outdated_exit_codes: Vec<i32>,
+    // a complete command (not prefixed with the executer) that prints the version, run before and after the update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd_for_version: Option<String>,
+    // finds the version in its output, the first group if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_pattern: Option<String>,
+    // arguments passed to the executer as they are, without a shell in between
+    #[serde(
+        default,
+        alias = "args",
+        deserialize_with = "list",
+        serialize_with = "bare_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub args_for_update: Option<Vec<String>>,
+    #[serde(
+        default,
+        deserialize_with = "list",
+        serialize_with = "bare_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub args_for_info: Option<Vec<String>>,
+    #[serde(
+        default,
+        deserialize_with = "list",
+        serialize_with = "bare_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub args_for_outdated: Option<Vec<String>>,
+    // the program and its arguments, replaces cmd_for_version
+    #[serde(
+        default,
+        deserialize_with = "list",
+        serialize_with = "bare_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub args_for_version: Option<Vec<String>>,
+    // run the cmd_for_* commands through the shell instead of splitting them into arguments
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub shell: bool,
+    // environment variables set or removed for the commands, values expand "~" and "$VAR"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_remove: Vec<String>,
+    // working directory of the commands, expands "~" and "$VAR" as well
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    // shell commands that run before and after cmd_for_update, a failing one stops the update
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before_update: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after_update: Vec<String>,
+    // timeout in seconds, the program gets killed after that
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    // regexes matched against every line of the output, in addition to the global ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub error_patterns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warning_patterns: Vec<String>,
+    // run a failed or timed out command again, waiting retry_delay seconds (doubled every time, up to 10 minutes) in between
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay: Option<u64>,
+    // names of programs that have to succeed before this one starts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+// a list like the other list fields, "Some([...])" from older configs still works
+fn list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    struct ListVisitor;
+
+    impl<'de> Visitor<'de> for ListVisitor {
+        type Value = Option<Vec<String>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a list of strings")
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            Vec::deserialize(deserializer).map(Some)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut list = Vec::new();
+            while let Some(item) = seq.next_element()? {
+                list.push(item);
+            }
+            Ok(Some(list))
+        }
+    }
+
+    deserializer.deserialize_any(ListVisitor)
+}
+
+fn bare_list<S: Serializer>(list: &Option<Vec<String>>, serializer: S) -> Result<S::Ok, S::Error> {
+    list.as_deref().unwrap_or_default().serialize(serializer)
+}
+
+impl App {
+    // replace the preset with the values it stands for
+    pub fn expanded(mut self) -> io::Result<App> {
+        let Some(name) = self.preset.take() else {
+            return Ok(self);
+        };
+        let preset = find_preset(&name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown preset {} (see \"up presets\")", name),
+            )
+        })?;
+
+        if self.name.is_empty() {
+            self.name = preset.name.to_string();
+        }
+        if self.executer.is_empty() {
+            self.executer = preset.executer.to_string();
+        }
+        // explicit args replace the command of the preset as well
+        if self.cmd_for_update.is_none() && self.args_for_update.is_none() {
+            self.cmd_for_update = Some(preset.cmd_for_update.to_string());
+        }
+        if self.cmd_for_info.is_none() && self.args_for_info.is_none() {
+            self.cmd_for_info = Some(preset.cmd_for_info.to_string());
+        }
+        if self.cmd_for_outdated.is_none() && self.args_for_outdated.is_none() {
+            self.cmd_for_outdated = preset.cmd_for_outdated.map(|cmd| cmd.to_string());
+            if self.outdated_exit_codes.is_empty() {
+                self.outdated_exit_codes = preset.outdated_exit_codes.to_vec();
+            }
+            if self.outdated_pattern.is_none() {
+                self.outdated_pattern = preset.outdated_pattern.map(|pattern| pattern.to_string());
+            }
+        }
+        self.shell |= preset.shell;
+
+        Ok(self)
+    }
+}
+
+// how a command gets started
+#[derive(Clone)]
+pub enum Cmd {
+    // the executer followed by its arguments, started without a shell
+    Direct(Vec<String>),
+    // a command line for the shell, started with "<shell> -c <cmd>"
+    Shell { shell: String, cmd: String },
+}
+
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cmd::Direct(argv) => write!(f, "{}", shell_words::join(argv)),
+            Cmd::Shell { shell, cmd } => write!(f, "{} -c {}", shell, shell_words::quote(cmd)),
+        }
+    }
+}
+
+impl Cmd {
+    pub fn command(&self) -> Command {
+        match self {
+            Cmd::Direct(argv) => {
+                // windows only finds "<name>.exe" on its own, npm and friends are "<name>.cmd" shims
+                let executer = match cfg!(target_os = "windows") {
+                    true => find_executable(&argv[0])
+                        .map(PathBuf::into_os_string)
+                        .unwrap_or_else(|| argv[0].clone().into()),
+                    false => argv[0].clone().into(),
+                };
+                let mut command = Command::new(executer);
+                command.args(&argv[1..]);
+                command
+            }
+            // both powershell and posix shells accept "-c <command>"
+            Cmd::Shell { shell, cmd } => {
+                let mut command = Command::new(shell);
+                command.args(["-c", cmd]);
+                command
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum State {
+    Pending,
+    Success,
+    Failed,
+    Skipped,
+    TimedOut,
+    // exit code 0, but the output matched a warning pattern
+    Warning,
 }
 
 #[derive(Clone)]
 pub struct Program {
     pub name: String,
     pub symbol: String,
+    pub executer: String,
     pub start_extern: bool,
     pub has_output: bool,
     pub outputfile: String,
-    pub update_cmd: Option<String>,
-    pub info_cmd: Option<String>,
+    pub update_cmd: Option<Cmd>,
+    pub info_cmd: Option<Cmd>,
+    pub outdated_cmd: Option<Cmd>,
+    pub outdated_pattern: Option<Regex>,
+    pub outdated_exit_codes: Vec<i32>,
+    // pending updates found by "up outdated"
+    pub pending: Option<usize>,
+    pub version_cmd: Option<Cmd>,
+    pub version_pattern: Regex,
+    // the versions found before and after the update
+    pub version_before: Option<String>,
+    pub version_after: Option<String>,
+    pub before_update: Vec<Cmd>,
+    pub after_update: Vec<Cmd>,
+    pub env: Vec<(String, String)>,
+    pub env_remove: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    pub retry_delay: Duration,
+    // how often the command ran in the end
+    pub attempts: u32,
+    // time spent on the program, including hooks and retries
+    pub duration: Option<Duration>,
+    pub error_patterns: Vec<Regex>,
+    pub warning_patterns: Vec<Regex>,
+    // the lines of the output that matched the error or warning patterns
+    pub matches: Vec<String>,
+    pub after: Vec<String>,
     pub msg: Vec<String>,
     pub placeholder: String,
+    pub state: State,
+    pub status: Option<ExitStatus>,
+    pub stdout: String,
+    pub stderr: String,
 }
 
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{}", self.name)
     }
 }
 
 impl Program {
-    pub fn new(
-        name: String,
-        symbol: Option<String>,
-        executer: String,
-        start_extern: bool,
-        has_output: bool,
-        cmd_for_update: Option<String>,
-        cmd_for_info: Option<String>,
-    ) -> Program {
-        let mut tmp = check_create_tmp_dir().unwrap_or_else(|err| {
+    fn new(app: App, shell: &str, config: &Config) -> io::Result<Program> {
+        let App {
+            preset: _,
+            name,
+            symbol,
+            executer,
+            start_extern,
+            has_output,
+            cmd_for_update,
+            cmd_for_info,
+            cmd_for_outdated,
+            outdated_pattern,
+            outdated_exit_codes,
+            cmd_for_version,
+            version_pattern,
+            args_for_update,
+            args_for_info,
+            args_for_outdated,
+            args_for_version,
+            shell: use_shell,
+            env,
+            env_remove,
+            cwd,
+            before_update,
+            after_update,
+            timeout,
+            retries,
+            retry_delay,
+            error_patterns: app_error_patterns,
+            warning_patterns: app_warning_patterns,
+            after,
+        } = app;
+
+        let tmp = check_create_tmp_dir().unwrap_or_else(|err| {
             error!("Unable to find or create a temporary directory: {err}");
             process::exit(1);
         });
-        tmp.push_str("up_output_");
-        tmp.push_str(&name);
-        let outputfile = tmp.to_string();
+        let datetime = Local::now().format("%d%m%Y_%H%M%S_%f").to_string();
+        let outputfile = Path::new(&tmp)
+            .join(format!("up_output_{}_{}.txt", name, datetime))
+            .display()
+            .to_string();
 
+        // shell builtins can't be started without the shell
+        let use_shell = use_shell || !start_extern;
         let update_cmd = Self::collect_cmds(
             &executer,
             start_extern,
-            has_output,
+            use_shell,
+            shell,
             cmd_for_update,
-            &outputfile,
-        );
+            args_for_update,
+        )
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?;
         let info_cmd = Self::collect_cmds(
             &executer,
             start_extern,
-            has_output,
+            use_shell,
+            shell,
             cmd_for_info,
-            &outputfile,
-        );
+            args_for_info,
+        )
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?;
+        let outdated_cmd = Self::collect_cmds(
+            &executer,
+            start_extern,
+            use_shell,
+            shell,
+            cmd_for_outdated,
+            args_for_outdated,
+        )
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?;
+        let outdated_pattern = compile_patterns(outdated_pattern.iter())
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?
+            .pop();
+        let version_cmd = Self::full_cmd(use_shell, shell, cmd_for_version, args_for_version)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?;
+        let version_pattern = version_pattern
+            .as_ref()
+            .or(config.version_pattern.as_ref())
+            .map(|pattern| pattern.as_str())
+            .unwrap_or(DEFAULT_VERSION_PATTERN);
+        let version_pattern = compile_patterns([version_pattern.to_string()].iter())
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?
+            .remove(0);
+
+        let before_update = hooks(shell, before_update);
+        let after_update = hooks(shell, after_update);
+
+        let env = env
+            .into_iter()
+            .map(|(key, value)| (key, expand(&value).0))
+            .collect();
+        let cwd = cwd.map(|cwd| PathBuf::from(expand(&cwd).0));
 
+        let error_patterns =
+            compile_patterns(config.error_patterns.iter().chain(&app_error_patterns))
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?;
+        let warning_patterns =
+            compile_patterns(config.warning_patterns.iter().chain(&app_warning_patterns))
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?;
+
+        let timeout = timeout.map(Duration::from_secs);
+        let retry_delay = Duration::from_secs(retry_delay.unwrap_or(DEFAULT_RETRY_DELAY));
         let msg = Vec::new();
         let placeholder = Self::get_placeholder(&name);
 
@@ -105,118 +490,692 @@ impl Program {
 
         let name = name.to_string();
 
-        Program {
+        Ok(Program {
             name,
             symbol,
+            executer,
             start_extern,
             has_output,
             outputfile,
             update_cmd,
             info_cmd,
+            outdated_cmd,
+            outdated_pattern,
+            outdated_exit_codes,
+            pending: None,
+            version_cmd,
+            version_pattern,
+            version_before: None,
+            version_after: None,
+            before_update,
+            after_update,
+            env,
+            env_remove,
+            cwd,
+            timeout,
+            retries,
+            retry_delay,
+            attempts: 0,
+            duration: None,
+            error_patterns,
+            warning_patterns,
+            matches: Vec::new(),
+            after,
             msg,
             placeholder,
+            state: State::Pending,
+            status: None,
+            stdout: String::new(),
+            stderr: String::new(),
+        })
+    }
+
+    // the command with the environment and working directory of this program
+    pub fn command(&self, cmd: &Cmd) -> Command {
+        let mut command = cmd.command();
+        for key in &self.env_remove {
+            command.env_remove(key);
+        }
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        command
+    }
+
+    // store the result of a finished command and write it to the output file
+    pub fn capture(&mut self, cmd: &Cmd, output: &Output) -> io::Result<()> {
+        self.status = Some(output.status);
+        self.state = match output.status.success() {
+            true => State::Success,
+            false => State::Failed,
+        };
+        self.stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        self.stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+        self.write_output(cmd, output)
+    }
+
+    // flag a successful command whose output matches an error or a warning pattern
+    pub fn scan_output(&mut self) {
+        if self.state != State::Success {
+            return;
+        }
+
+        let errors = matching_lines(&self.error_patterns, &self.stdout, &self.stderr);
+        if !errors.is_empty() {
+            error!(
+                "{} failed with errors in the output: {}",
+                self.name,
+                errors.join(" | ")
+            );
+            self.state = State::Failed;
+            self.matches = errors;
+            return;
+        }
+        let warnings = matching_lines(&self.warning_patterns, &self.stdout, &self.stderr);
+        if !warnings.is_empty() {
+            warn!(
+                "{} completed with warnings: {}",
+                self.name,
+                warnings.join(" | ")
+            );
+            self.state = State::Warning;
+            self.matches = warnings;
+        }
+    }
+
+    // the version in the output of cmd_for_version, stdout first
+    pub fn parse_version(&self, output: &Output) -> Option<String> {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        [stdout, stderr].iter().find_map(|text| {
+            self.version_pattern.captures(text).map(|captures| {
+                captures
+                    .get(1)
+                    .or(captures.get(0))
+                    .map(|version| version.as_str().to_string())
+                    .unwrap_or_default()
+            })
+        })
+    }
+
+    // count the pending updates in the output of cmd_for_outdated
+    pub fn count_pending(&mut self) {
+        let pending = match &self.outdated_pattern {
+            Some(pattern) => self
+                .stdout
+                .lines()
+                .filter_map(|line| pattern.captures(line))
+                .map(|captures| match captures.get(1) {
+                    Some(number) => number.as_str().parse().unwrap_or(1),
+                    None => 1,
+                })
+                .sum(),
+            None => self
+                .stdout
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .count(),
+        };
+        self.pending = Some(pending);
+    }
+
+    // append a finished command to the output file, hooks end up next to the update
+    pub fn write_output(&self, cmd: &Cmd, output: &Output) -> io::Result<()> {
+        if !self.has_output {
+            return Ok(());
+        }
+
+        let exit_code = match output.status.code() {
+            Some(code) => code.to_string(),
+            None => "none (terminated)".to_string(),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.outputfile)?;
+        write!(
+            file,
+            "$ {}\nexit code: {}\n\n--- stdout ---\n{}\n--- stderr ---\n{}\n",
+            cmd,
+            exit_code,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+    }
+
+    // a complete command line, split into arguments unless it needs the shell
+    fn full_cmd(
+        use_shell: bool,
+        shell: &str,
+        cmd: Option<String>,
+        args: Option<Vec<String>>,
+    ) -> io::Result<Option<Cmd>> {
+        if let Some(args) = args {
+            return Ok((!args.is_empty()).then_some(Cmd::Direct(args)));
+        }
+
+        match cmd {
+            Some(cmd) if use_shell => Ok(Some(Cmd::Shell {
+                shell: shell.to_string(),
+                cmd,
+            })),
+            Some(cmd) => {
+                let argv = shell_words::split(&cmd).map_err(|err| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unable to split \"{}\" into arguments: {}", cmd, err),
+                    )
+                })?;
+                Ok((!argv.is_empty()).then_some(Cmd::Direct(argv)))
+            }
+            None => Ok(None),
         }
     }
 
     fn collect_cmds(
-        executer: &String,
+        executer: &str,
         start_extern: bool,
-        has_output: bool,
+        use_shell: bool,
+        shell: &str,
         cmd: Option<String>,
-        outputfile: &String,
-    ) -> Option<String> {
-        let datetime = Local::now().format("%d%m%Y_%H%M%S_%f").to_string();
-        let mut output = String::new();
-        output.push_str(&outputfile);
-        output.push_str("_");
-        output.push_str(datetime.as_str());
-        output.push_str(".txt");
+        args: Option<Vec<String>>,
+    ) -> io::Result<Option<Cmd>> {
+        if let Some(args) = args {
+            let mut argv = vec![executer.to_string()];
+            argv.extend(args);
+            return Ok(Some(Cmd::Direct(argv)));
+        }
+
+        let Some(cmd) = cmd else {
+            return Ok(None);
+        };
+
+        if !use_shell {
+            // split like a shell would, but without expanding or interpreting anything
+            let mut argv = vec![executer.to_string()];
+            argv.extend(shell_words::split(&cmd).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unable to split \"{}\" into arguments: {}", cmd, err),
+                )
+            })?);
+            return Ok(Some(Cmd::Direct(argv)));
+        }
 
         let mut collected_cmds = String::new();
-        match cmd {
-            Some(cmd) => match start_extern {
-                true => {
-                    collected_cmds.push_str("Start-Process ");
-                    collected_cmds.push_str(&executer);
-                    collected_cmds.push_str(" -ArgumentList '");
-                    collected_cmds.push_str(&cmd);
-                    collected_cmds.push_str("'");
-                    if has_output {
-                        collected_cmds.push_str("-RedirectStandardOutput ");
-                        collected_cmds.push_str(output.as_str());
-                    }
-                    collected_cmds.push_str(" -WindowStyle Hidden -Wait;");
-                }
-                false => {
-                    collected_cmds.push_str(&executer);
-                    collected_cmds.push_str(" ");
-                    collected_cmds.push_str(&cmd);
-                    collected_cmds.push_str(";");
-                }
-            },
-            None => return None,
-        }
-
-        Some(collected_cmds)
+        match start_extern {
+            true if cfg!(target_os = "windows") => {
+                // call the external program directly instead of "Start-Process",
+                // so that its output and exit code reach us
+                collected_cmds.push_str("& ");
+                collected_cmds.push_str(executer);
+                collected_cmds.push(' ');
+                collected_cmds.push_str(&cmd);
+                collected_cmds.push_str("; exit $LASTEXITCODE");
+            }
+            _ => {
+                collected_cmds.push_str(executer);
+                collected_cmds.push(' ');
+                collected_cmds.push_str(&cmd);
+                collected_cmds.push(';');
+            }
+        }
+
+        Ok(Some(Cmd::Shell {
+            shell: shell.to_string(),
+            cmd: collected_cmds,
+        }))
     }
 
     fn get_placeholder(name: &str) -> String {
         let mut holder = String::new();
-        let rest_length = PLACEHOLDER_THRESHOLD - name.len();
+        // longer names just push the spinner to the right
+        let rest_length = PLACEHOLDER_THRESHOLD.saturating_sub(name.chars().count());
         for _ in 0..rest_length {
-            holder.push_str(" ");
+            holder.push(' ');
         }
 
         holder
     }
 }
 
-pub fn load_programs(path: &PathBuf) -> io::Result<Vec<Program>> {
+// expand a leading "~" to the home directory and "$VAR" or "${VAR}" to its value
+// unset variables expand to nothing, like in a posix shell, and are returned as well
+pub fn expand(value: &str) -> (String, Vec<String>) {
+    let mut expanded = String::new();
+    let mut unset = Vec::new();
+
+    let mut rest = value;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        if let Some(home) = dirs::home_dir() {
+            expanded.push_str(&home.display().to_string());
+            rest = &rest[1..];
+        }
+    }
+
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        let braced = chars.next_if_eq(&'{').is_some();
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            name.push(c);
+        }
+        if braced && chars.next_if_eq(&'}').is_none() {
+            // not a variable after all, keep it as it is
+            expanded.push_str("${");
+            expanded.push_str(&name);
+            continue;
+        }
+        if name.is_empty() {
+            expanded.push('$');
+            if braced {
+                expanded.push_str("{}");
+            }
+            continue;
+        }
+
+        match env::var(&name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => unset.push(name),
+        }
+    }
+
+    (expanded, unset)
+}
+
+// the usage notes on top of every generated config file, followed by the given content
+pub fn config_template(body: &str) -> String {
+    format!(
+        "// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n{}",
+        "Usage:\n",
+        "App(",
+        "\tname: \"example\",",
+        "\tsymbol: None,",
+        "\texecuter: \"example\",",
+        "\tstart_extern: true,",
+        "\thas_output: true,",
+        "\tcmd_for_update: Some(\"-c example update --all\"),",
+        "\tcmd_for_info: Some(\"-c example status\"),",
+        "),\n",
+        "Values to replace:",
+        "<name>           => a custom name for the program",
+        "<symbol>         => if symbol is \"None\", the first character of the name will be used; options: [Some(\"<symbol>\"), None]",
+        "<excuter>        => the actual program to call from the command line (often the name of the program itself)",
+        "<start_extern>   => should only be \"false\" if the command is a builtin of the shell and no external program starts (always runs through the shell then); options [true, false]",
+        "<has_output>     => used to write the output (stdout, stderr and exit code) in a temporary file for later reference; options [true, false]",
+        "<cmd_for_update> => the actual command to update the program; options: [Some(\"<cmd_for_update>\"), None]",
+        "<cmd_for_info>   => the actual command to get status information about the program; options: [Some(\"<cmd_for_info>\"), None]",
+        "Optional per app:",
+        "<preset>         => a known package manager (see \"up presets\"), fills in every field that is not set; e.g. App(preset: Some(\"rustup\")); options: [Some(\"<preset>\"), None]",
+        "<timeout>        => seconds after which the program and all its child processes get killed; options: [Some(<seconds>), None]",
+        "<error_patterns> => regexes for lines in the output that turn a successful update into a failed one; options: [[\"<regex>\", ...]]",
+        "<warning_patterns>=> regexes for lines in the output that mark the update as \"completed with warnings\"; options: [[\"<regex>\", ...]]",
+        "<retries>        => how often a failed or timed out command runs again; options: [Some(<number>), None]",
+        "<retry_delay>    => seconds to wait before the first retry, doubled for every further one up to 10 minutes; defaults to 5; options: [Some(<seconds>), None]",
+        "<after>          => names of programs that have to be updated successfully before this one starts; options: [[\"<name>\", ...]]",
+        "<args_for_update>=> the arguments to update the program, passed on as they are; replaces cmd_for_update; options: [[\"<arg>\", ...]]",
+        "<args_for_info>  => the arguments to get status information, passed on as they are; replaces cmd_for_info; options: [[\"<arg>\", ...]]",
+        "<cmd_for_outdated>=> lists the pending updates for \"up outdated\", every non-empty line counts as one; options: [Some(\"<cmd_for_outdated>\"), None]",
+        "<outdated_exit_codes>=> exit codes besides 0 that cmd_for_outdated uses when it worked (dnf: 100, npm: 1); options: [[<code>, ...]]",
+        "<outdated_pattern>=> only lines of cmd_for_outdated matching this regex are counted, or the number in its first group; options: [Some(\"<regex>\"), None]",
+        "<args_for_outdated>=> the arguments to list the pending updates, passed on as they are; replaces cmd_for_outdated; options: [[\"<arg>\", ...]]",
+        "<cmd_for_version>=> a complete command that prints the version (e.g. \"rustc --version\"), run before and after the update; options: [Some(\"<cmd>\"), None]",
+        "<args_for_version>=> the program and its arguments to print the version, replaces cmd_for_version; options: [[\"<program>\", \"<arg>\", ...]]",
+        "<version_pattern>=> regex that finds the version in that output, the first group if it has one; defaults to dotted numbers like \"1.75.0\"; options: [Some(\"<regex>\"), None]",
+        "<shell>          => run the cmd_for_* commands through the shell (needed for pipes, \"&&\", variables, ...); otherwise they are split into arguments; options: [true, false]",
+        "<env>            => environment variables for the commands, values expand \"~\" and \"$VAR\"; options: [{\"<key>\": \"<value>\", ...}]",
+        "<env_remove>     => environment variables removed for the commands; options: [[\"<key>\", ...]]",
+        "<cwd>            => the working directory of the commands, expands \"~\" and \"$VAR\"; options: [Some(\"<path>\"), None]",
+        "<before_update>  => shell commands that run before cmd_for_update, the update is skipped if one fails; options: [[\"<cmd>\", ...]]",
+        "<after_update>   => shell commands that run after a successful update; options: [[\"<cmd>\", ...]]",
+        "Optional in the root (next to \"apps\"):",
+        "<shell>          => the shell used for commands with \"shell: true\"; defaults to \"powershell\" on windows and \"sh\" everywhere else; options: [Some(\"<shell>\"), None]",
+        "<max_parallel>   => how many programs are updated at the same time; all at once if \"None\"; options: [Some(<number>), None]",
+        "<before_all>     => shell commands that run before the first update, nothing is updated if one fails; options: [[\"<cmd>\", ...]]",
+        "<after_all>      => shell commands that run after the last update; options: [[\"<cmd>\", ...]]",
+        "<error_patterns> => regexes used for every program in addition to its own ones; options: [[\"<regex>\", ...]]",
+        "<warning_patterns>=> regexes used for every program in addition to its own ones; options: [[\"<regex>\", ...]]",
+        "<version_pattern>=> regex that finds the version for every program without its own; options: [Some(\"<regex>\"), None]",
+        "<keep_runs>      => how many output files are kept per program, older ones are removed after every run; options: [Some(<number>), None]",
+        "<keep_days>      => output files older than this many days are removed after every run; options: [Some(<days>), None]\n",
+        body
+    )
+}
+
+// the config file with a single example program
+pub fn default_config() -> String {
+    config_template(
+        "(\n \tapps: [\n \t\tApp(\n \t\t\tname: \"example\",\n \t\t\tsymbol: None,\n \t\t\texecuter: \"example\",\n \t\t\tstart_extern: true,\n \t\t\thas_output: true,\n \t\t\tcmd_for_update: None,\n \t\t\tcmd_for_info: None,\n \t\t),\n \t],\n)",
+    )
+}
+
+pub fn load_programs(path: &PathBuf) -> io::Result<(Vec<Program>, Settings)> {
     if !path.as_path().exists() {
-        let default_content = format!(
-            "// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n// {}\n{}",
-            "Usage:\n",
-            "App(",
-            "\tname: \"example\",",
-            "\tsymbol: None,",
-            "\texecuter: \"example\",",
-            "\tstart_extern: true,",
-            "\thas_output: true,",
-            "\tcmd_for_update: Some(\"-c example update --all\"),",
-            "\tcmd_for_info: Some(\"-c example status\"),",
-            "),\n",
-            "Values to replace:",
-            "<name>           => a custom name for the program",
-            "<symbol>         => if symbol is \"None\", the first character of the name will be used; options: [Some(\"<symbol>\"), None]",
-            "<excuter>        => the actual program to call from the command line (often the name of the program itself)",
-            "<start_extern>   => should only be \"false\" if no output will be produced and no external program starts; options [true, false]",
-            "<has_output>     => used to write the output in a temporary file for later reference; options [true, false]",
-            "<cmd_for_update> => the actual command to update the program; options: [Some(\"<cmd_for_update>\"), None]",
-            "<cmd_for_info>   => the actual command to get status information about the program; options: [Some(\"<cmd_for_info>\"), None]\n",
-            "(\n \tapps: [\n \t\tApp(\n \t\t\tname: \"example\",\n \t\t\tsymbol: None,\n \t\t\texecuter: \"example\",\n \t\t\tstart_extern: true,\n \t\t\thas_output: true,\n \t\t\tcmd_for_update: None,\n \t\t\tcmd_for_info: None,\n \t\t),\n \t],\n)"
-        );
-        fs::write(&path, default_content)?;
+        fs::write(path, default_config())?;
     }
 
     let file = File::open(path)?;
-    let config: Config = ron::de::from_reader(file).unwrap_or_else(|err| {
-        error!("Unable to read ron file {}: {}", path.display(), err);
-        process::exit(1);
-    });
+    let mut config: Config = ron::de::from_reader(file).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} (run \"up check\" for details)", err),
+        )
+    })?;
+    config.apps = expand_presets(config.apps)?;
+    check_dependencies(&config.apps)?;
+
+    let shell = config
+        .shell
+        .clone()
+        .unwrap_or_else(|| default_shell().to_string());
 
     let mut programs = Vec::new();
-    for app in config.apps {
-        let program = Program::new(
-            app.name,
-            app.symbol,
-            app.executer,
-            app.start_extern,
-            app.has_output,
-            app.cmd_for_update,
-            app.cmd_for_info,
-        );
+    for app in std::mem::take(&mut config.apps) {
+        let program = Program::new(app, &shell, &config)?;
         programs.push(program);
     }
 
-    Ok(programs)
+    let settings = Settings {
+        max_parallel: config.max_parallel,
+        before_all: hooks(&shell, config.before_all),
+        after_all: hooks(&shell, config.after_all),
+        keep_runs: config.keep_runs,
+        keep_days: config.keep_days,
+        format: Format::default(),
+        plain: false,
+        stream: None,
+    };
+
+    Ok((programs, settings))
+}
+
+fn compile_patterns<'a>(patterns: impl Iterator<Item = &'a String>) -> io::Result<Vec<Regex>> {
+    patterns
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid pattern \"{}\": {}", pattern, err),
+                )
+            })
+        })
+        .collect()
+}
+
+// every line of stdout and stderr that matches one of the patterns, without surrounding whitespace
+fn matching_lines(patterns: &[Regex], stdout: &str, stderr: &str) -> Vec<String> {
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+
+    stdout
+        .lines()
+        .chain(stderr.lines())
+        .filter(|line| patterns.iter().any(|pattern| pattern.is_match(line)))
+        .map(|line| line.trim().to_string())
+        .collect()
+}
+
+// hooks are small scripts, so they always run through the shell
+fn hooks(shell: &str, cmds: Vec<String>) -> Vec<Cmd> {
+    cmds.into_iter()
+        .map(|cmd| Cmd::Shell {
+            shell: shell.to_string(),
+            cmd,
+        })
+        .collect()
+}
+
+fn default_shell() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "powershell"
+    } else {
+        "sh"
+    }
+}
+
+// make sure the content of a config file would load, used before it gets overwritten
+pub fn check_config(content: &str) -> io::Result<()> {
+    let config: Config = ron::de::from_str(content)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    check_dependencies(&expand_presets(config.apps)?)
+}
+
+fn expand_presets(apps: Vec<App>) -> io::Result<Vec<App>> {
+    let apps = apps
+        .into_iter()
+        .map(App::expanded)
+        .collect::<io::Result<Vec<_>>>()?;
+    if let Some(app) = apps.iter().find(|app| app.name.is_empty()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Program without a name (executer: \"{}\")", app.executer),
+        ));
+    }
+
+    Ok(apps)
+}
+
+// check the config file without running anything, returns the errors and the warnings found
+pub fn validate_config(path: &Path) -> io::Result<(Vec<String>, Vec<String>)> {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    let content = fs::read_to_string(path)?;
+    let mut config: Config = match ron::de::from_str(&content) {
+        Ok(config) => config,
+        Err(err) => {
+            errors.push(format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                err.position.line,
+                err.position.col,
+                err.code
+            ));
+            return Ok((errors, warnings));
+        }
+    };
+
+    config.apps = match expand_presets(config.apps) {
+        Ok(apps) => apps,
+        Err(err) => {
+            errors.push(err.to_string());
+            return Ok((errors, warnings));
+        }
+    };
+    if let Err(err) = check_dependencies(&config.apps) {
+        errors.push(err.to_string());
+    }
+    let patterns = config.error_patterns.iter().chain(&config.warning_patterns);
+    for pattern in patterns.chain(&config.version_pattern) {
+        if let Err(err) = Regex::new(pattern) {
+            errors.push(format!("invalid pattern \"{}\": {}", pattern, err));
+        }
+    }
+    if config
+        .before_all
+        .iter()
+        .chain(&config.after_all)
+        .any(|cmd| cmd.trim().is_empty())
+    {
+        warnings.push("empty command in before_all or after_all".to_string());
+    }
+
+    let mut seen = HashSet::new();
+    for app in &config.apps {
+        if !seen.insert(app.name.as_str()) {
+            warnings.push(format!("{}: duplicate name", app.name));
+        }
+        if app.name.chars().count() > PLACEHOLDER_THRESHOLD {
+            warnings.push(format!(
+                "{}: name is longer than {} characters, the output will be misaligned",
+                app.name, PLACEHOLDER_THRESHOLD
+            ));
+        }
+        if app.executer.trim().is_empty() {
+            errors.push(format!("{}: executer is empty", app.name));
+        } else if !executable_exists(app.executer.trim()) {
+            warnings.push(format!(
+                "{}: executer \"{}\" not found in PATH",
+                app.name, app.executer
+            ));
+        }
+        let values = app.env.values().chain(app.cwd.iter());
+        for name in values.flat_map(|value| expand(value).1) {
+            warnings.push(format!(
+                "{}: environment variable ${} is not set",
+                app.name, name
+            ));
+        }
+        let patterns = app.error_patterns.iter().chain(&app.warning_patterns);
+        for pattern in patterns
+            .chain(&app.outdated_pattern)
+            .chain(&app.version_pattern)
+        {
+            if let Err(err) = Regex::new(pattern) {
+                errors.push(format!(
+                    "{}: invalid pattern \"{}\": {}",
+                    app.name, pattern, err
+                ));
+            }
+        }
+        if app
+            .before_update
+            .iter()
+            .chain(&app.after_update)
+            .any(|cmd| cmd.trim().is_empty())
+        {
+            warnings.push(format!("{}: empty hook command", app.name));
+        }
+        if let Some(cwd) = &app.cwd {
+            let dir = expand(cwd).0;
+            if !Path::new(&dir).is_dir() {
+                warnings.push(format!("{}: cwd \"{}\" is not a directory", app.name, dir));
+            }
+        }
+        for (field, cmd) in [
+            ("cmd_for_update", &app.cmd_for_update),
+            ("cmd_for_info", &app.cmd_for_info),
+            ("cmd_for_outdated", &app.cmd_for_outdated),
+        ] {
+            if cmd.as_ref().is_some_and(|cmd| cmd.trim().is_empty()) {
+                warnings.push(format!(
+                    "{}: {} is empty, use None instead",
+                    app.name, field
+                ));
+            }
+        }
+        for (field, cmd, args) in [
+            ("cmd_for_update", &app.cmd_for_update, &app.args_for_update),
+            ("cmd_for_info", &app.cmd_for_info, &app.args_for_info),
+            (
+                "cmd_for_outdated",
+                &app.cmd_for_outdated,
+                &app.args_for_outdated,
+            ),
+        ] {
+            let Some(cmd) = cmd else {
+                continue;
+            };
+            if args.is_some() {
+                warnings.push(format!(
+                    "{}: {} is ignored, the args for the same command are used",
+                    app.name, field
+                ));
+            } else if app.shell || !app.start_extern {
+                continue;
+            } else if let Err(err) = shell_words::split(cmd) {
+                errors.push(format!("{}: unable to split {}: {}", app.name, field, err));
+            } else if ["&&", "||", "|", ";", ">", "<", "$", "`"]
+                .iter()
+                .any(|token| cmd.contains(token))
+            {
+                warnings.push(format!(
+                    "{}: {} uses shell syntax but runs without a shell, add \"shell: true\"",
+                    app.name, field
+                ));
+            }
+        }
+        if app.cmd_for_update.is_none()
+            && app.cmd_for_info.is_none()
+            && app.args_for_update.is_none()
+            && app.args_for_info.is_none()
+        {
+            warnings.push(format!(
+                "{}: no command for update or info is set",
+                app.name
+            ));
+        }
+    }
+
+    Ok((errors, warnings))
+}
+
+// reject dependencies on unknown programs and dependency cycles
+fn check_dependencies(programs: &[App]) -> io::Result<()> {
+    let names: HashSet<_> = programs.iter().map(|p| p.name.as_str()).collect();
+    for program in programs {
+        for dep in &program.after {
+            if !names.contains(dep.as_str()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} depends on unknown program {}", program.name, dep),
+                ));
+            }
+        }
+    }
+
+    // repeatedly remove every program whose dependencies are all removed,
+    // whatever is left over is part of a cycle
+    let mut resolved = HashSet::new();
+    loop {
+        let next: Vec<_> = programs
+            .iter()
+            .filter(|p| !resolved.contains(p.name.as_str()))
+            .filter(|p| p.after.iter().all(|dep| resolved.contains(dep.as_str())))
+            .map(|p| p.name.as_str())
+            .collect();
+        if next.is_empty() {
+            break;
+        }
+        resolved.extend(next);
+    }
+
+    let cycle: Vec<_> = programs
+        .iter()
+        .filter(|p| !resolved.contains(p.name.as_str()))
+        .map(|p| p.name.as_str())
+        .collect();
+    match cycle.is_empty() {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Dependency cycle between: {}", cycle.join(", ")),
+        )),
+    }
+}
+
+// programs with their own number of retries in the config keep it
+pub fn apply_retries(programs: &mut [Program], retries: u32) {
+    for program in programs.iter_mut() {
+        if program.retries.is_none() {
+            program.retries = Some(retries);
+        }
+    }
+}
+
+// use the global timeout for every program without its own timeout
+pub fn apply_timeout(programs: &mut [Program], timeout: Duration) {
+    for program in programs.iter_mut() {
+        if program.timeout.is_none() {
+            program.timeout = Some(timeout);
+        }
+    }
 }
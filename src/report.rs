@@ -0,0 +1,94 @@
+use clap::{builder::PossibleValue, ValueEnum};
+use regex::Regex;
+use serde::Serialize;
+
+use std::{borrow::Cow, sync::OnceLock};
+
+// how results are printed, chosen with the global --format flag
+// the json layouts are documented in the README and only get new fields, never lose any
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Text,
+    // one pretty printed document
+    Json,
+    // one compact object per line
+    Jsonl,
+}
+
+impl Format {
+    pub fn is_text(&self) -> bool {
+        *self == Format::Text
+    }
+}
+
+impl ValueEnum for Format {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Format::Text, Format::Json, Format::Jsonl]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Format::Text => "text",
+            Format::Json => "json",
+            Format::Jsonl => "jsonl",
+        }))
+    }
+}
+
+// how the output of running programs is shown live, chosen with --stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    // every line, prefixed with the program name
+    Lines,
+    // only the latest line, next to the spinner
+    Spinner,
+}
+
+impl ValueEnum for Stream {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Stream::Lines, Stream::Spinner]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Stream::Lines => "lines",
+            Stream::Spinner => "spinner",
+        }))
+    }
+}
+
+// a single value, pretty printed or on one line
+pub fn print_value<T: Serialize>(value: &T, format: Format) {
+    let json = match format {
+        Format::Json => serde_json::to_string_pretty(value),
+        _ => serde_json::to_string(value),
+    };
+    println!("{}", json.expect("plain data always serializes"));
+}
+
+// a list, as one array or as one line per entry
+pub fn print_entries<T: Serialize>(entries: &[T], format: Format) {
+    match format {
+        Format::Jsonl => {
+            for entry in entries {
+                print_value(entry, format);
+            }
+        }
+        _ => print_value(&entries, format),
+    }
+}
+
+// a line for people, without colors in plain mode so cron and ci logs stay readable
+pub fn print_text(line: &str, plain: bool) {
+    match plain {
+        true => println!("{}", strip_colors(line)),
+        false => println!("{}", line),
+    }
+}
+
+pub fn strip_colors(text: &str) -> Cow<'_, str> {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    ANSI.get_or_init(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap())
+        .replace_all(text, "")
+}